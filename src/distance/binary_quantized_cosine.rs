@@ -68,6 +68,15 @@ impl Distance for BinaryQuantizedCosine {
         d
     }
 
+    /// Returns the cosine similarity of the quantized vectors in `[-1, 1]`.
+    fn similarity(distance: f32, _dimensions: usize) -> f32 {
+        1.0 - distance * 2.0
+    }
+
+    fn relevance(similarity: f32) -> f32 {
+        ((1.0 + similarity) / 2.0).clamp(0.0, 1.0)
+    }
+
     fn norm_no_header(v: &UnalignedVector<Self::VectorCodec>) -> f32 {
         dot_product_binary_quantized(v, v).sqrt()
     }
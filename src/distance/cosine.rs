@@ -62,6 +62,21 @@ impl Distance for Cosine {
         d
     }
 
+    /// Returns the cosine similarity in `[-1, 1]`.
+    fn similarity(distance: f32, _dimensions: usize) -> f32 {
+        1.0 - distance * 2.0
+    }
+
+    fn relevance(similarity: f32) -> f32 {
+        ((1.0 + similarity) / 2.0).clamp(0.0, 1.0)
+    }
+
+    fn query_vector(leaf: &Leaf<Self>) -> Vec<f32> {
+        let mut leaf = leaf.clone();
+        Self::normalize(&mut leaf);
+        leaf.vector.to_vec()
+    }
+
     fn norm_no_header(v: &UnalignedVector<Self::VectorCodec>) -> f32 {
         dot_product(v, v).sqrt()
     }
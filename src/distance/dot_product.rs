@@ -82,6 +82,21 @@ impl Distance for DotProduct {
         -d
     }
 
+    fn similarity(distance: f32, _dimensions: usize) -> f32 {
+        // The normalized distance is already the raw dot product.
+        distance
+    }
+
+    fn relevance(similarity: f32) -> f32 {
+        // The dot product is unbounded, the logistic function keeps its order.
+        1.0 / (1.0 + (-similarity).exp())
+    }
+
+    fn is_within(distance: f32, max_distance: f32) -> bool {
+        // The normalized distance is the dot product itself, the higher the closer.
+        distance >= max_distance
+    }
+
     fn normalize(node: &mut Leaf<Self>) {
         let norm = Self::norm(node);
         if norm > 0.0 {
@@ -163,4 +178,16 @@ impl Distance for DotProduct {
 
         Ok(())
     }
+
+    fn preprocess_leaf(leaf: &mut Leaf<Self>, preprocessed: &Leaf<Self>) -> bool {
+        // Every preprocessed leaf stores the squared max norm of the items.
+        let squared_max_norm = preprocessed.header.norm;
+        let squared_norm = dot_product(&leaf.vector, &leaf.vector);
+        if squared_norm > squared_max_norm {
+            return false;
+        }
+        leaf.header.norm = squared_max_norm;
+        leaf.header.extra_dim = (squared_max_norm - squared_norm).sqrt();
+        true
+    }
 }
@@ -31,6 +31,9 @@ impl fmt::Debug for NodeHeaderEuclidean {
 }
 
 impl Distance for Euclidean {
+    // The normal is a unit vector, the margin is the signed distance to the split plane.
+    const MARGIN_BOUNDS_DISTANCE: bool = true;
+
     type Header = NodeHeaderEuclidean;
     type VectorCodec = f32;
 
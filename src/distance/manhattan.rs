@@ -30,6 +30,9 @@ impl fmt::Debug for NodeHeaderManhattan {
 }
 
 impl Distance for Manhattan {
+    // The normal is a unit vector and the manhattan distance is never shorter than the euclidean one.
+    const MARGIN_BOUNDS_DISTANCE: bool = true;
+
     type Header = NodeHeaderManhattan;
     type VectorCodec = f32;
 
@@ -40,8 +40,13 @@ fn new_leaf<D: Distance>(vec: Vec<f32>) -> Leaf<'static, D> {
 pub trait Distance: Send + Sync + Sized + Clone + fmt::Debug + 'static {
     const DEFAULT_OVERSAMPLING: usize = 1;
 
+    /// Whether the absolute [`Self::margin`] between a query and a split plane is a lower bound of the
+    /// normalized distance between the query and the items on the other side of the plane.
+    /// It lets the queries limited to a radius skip the branches of the trees that are out of it.
+    const MARGIN_BOUNDS_DISTANCE: bool = false;
+
     /// A header structure with informations related to the
-    type Header: Pod + Zeroable + fmt::Debug;
+    type Header: Pod + Zeroable + fmt::Debug + Send + Sync;
     type VectorCodec: UnalignedVectorCodec;
 
     fn name() -> &'static str;
@@ -60,6 +65,24 @@ pub trait Distance: Send + Sync + Sized + Clone + fmt::Debug + 'static {
         d.sqrt()
     }
 
+    /// Converts a normalized distance into a similarity, the higher the closer.
+    /// By default the distance is mapped into `]0, 1]`, `1` being an exact match.
+    fn similarity(distance: f32, _dimensions: usize) -> f32 {
+        1.0 / (1.0 + distance)
+    }
+
+    /// Maps a [`Self::similarity`] into `[0, 1]`, the higher the closer, so that the results
+    /// of indexes using different distances can be weighted and merged by a [`crate::MultiQuery`].
+    /// By default the similarity is already in `]0, 1]`.
+    fn relevance(similarity: f32) -> f32 {
+        similarity
+    }
+
+    /// Returns `true` if the normalized `distance` is not farther than the normalized `max_distance`.
+    fn is_within(distance: f32, max_distance: f32) -> bool {
+        distance <= max_distance
+    }
+
     fn pq_distance(distance: f32, margin: f32, side: Side) -> f32 {
         match side {
             Side::Left => (-margin).min(distance),
@@ -83,6 +106,12 @@ pub trait Distance: Send + Sync + Sized + Clone + fmt::Debug + 'static {
 
     fn init(node: &mut Leaf<Self>);
 
+    /// Returns the vector of a leaf as it must be combined with other leafs to build a query.
+    /// The distances that only care about the direction of the vectors normalize it first.
+    fn query_vector(leaf: &Leaf<Self>) -> Vec<f32> {
+        leaf.vector.to_vec()
+    }
+
     fn update_mean(mean: &mut Leaf<Self>, new_node: &Leaf<Self>, norm: f32, c: f32) {
         let vec: Vec<_> = mean
             .vector
@@ -118,6 +147,13 @@ pub trait Distance: Send + Sync + Sized + Clone + fmt::Debug + 'static {
         Ok(())
     }
 
+    /// Prepares the header of a `leaf` inserted in an index whose items were already
+    /// [preprocessed](Self::preprocess), `preprocessed` being one of them. Returns `false`
+    /// if the leaf changes the preprocessing and all the items must be preprocessed again.
+    fn preprocess_leaf(_leaf: &mut Leaf<Self>, _preprocessed: &Leaf<Self>) -> bool {
+        true
+    }
+
     fn size_of_item(dimensions: usize) -> usize {
         std::mem::size_of::<Self::Header>() + Self::VectorCodec::size_of_item(dimensions)
     }
@@ -22,6 +22,15 @@ pub enum Error {
         received: usize,
     },
 
+    /// The user provided a number of weights that doesn't match the number of queries.
+    #[error("Invalid number of weights. Got {received} but expected {expected}")]
+    InvalidWeights {
+        /// The number of queries.
+        expected: usize,
+        /// The number of weights given by the user.
+        received: usize,
+    },
+
     /// An internal error returned when arroy cannot generate internal IDs.
     #[error("Database full. Arroy cannot generate enough internal IDs for your items")]
     DatabaseFull,
@@ -48,6 +48,16 @@ impl Key {
     pub const fn tree(index: u16, item: u32) -> Self {
         Self::new(index, NodeId::tree(item))
     }
+
+    /// Encodes the key without allocating, see [`KeyCodec`].
+    pub fn to_bytes(self) -> [u8; size_of::<u64>()] {
+        let mut output = [0; size_of::<u64>()];
+        output[0..2].copy_from_slice(&self.index.to_be_bytes());
+        output[2] = self.node.mode as u8;
+        output[3..7].copy_from_slice(&self.node.item.to_be_bytes());
+        output[7] = self._padding;
+        output
+    }
 }
 
 /// The heed codec used internally to encode/decoding the internal key type.
@@ -57,13 +67,7 @@ impl<'a> heed::BytesEncode<'a> for KeyCodec {
     type EItem = Key;
 
     fn bytes_encode(item: &'a Self::EItem) -> Result<Cow<'a, [u8]>, BoxedError> {
-        let mut output = Vec::with_capacity(size_of::<u64>());
-        output.extend_from_slice(&item.index.to_be_bytes());
-        output.extend_from_slice(&(item.node.mode as u8).to_be_bytes());
-        output.extend_from_slice(&item.node.item.to_be_bytes());
-        output.extend_from_slice(&item._padding.to_be_bytes());
-
-        Ok(Cow::Owned(output))
+        Ok(Cow::Owned(item.to_bytes().to_vec()))
     }
 }
 
@@ -103,9 +103,12 @@ use key::{Key, Prefix, PrefixCodec};
 use metadata::{Metadata, MetadataCodec};
 use node::{Node, NodeCodec};
 use node_id::{NodeId, NodeMode};
-pub use reader::{QueryBuilder, Reader};
-pub use stats::{Stats, TreeStats};
-pub use writer::{ArroyBuilder, MainStep, SubStep, Writer, WriterProgress};
+pub use reader::{
+    Aggregation, MultiQuery, NnsContinuation, NnsIter, QueryBuilder, QueryScratch, Reader,
+    SearchStrategy,
+};
+pub use stats::{QueryTrace, Stats, TreeStats};
+pub use writer::{ArroyBuilder, MainStep, PendingUpdates, SubStep, Writer, WriterProgress};
 
 /// The set of types used by the [`Distance`] trait.
 pub mod internals {
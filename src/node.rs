@@ -9,6 +9,7 @@ use roaring::RoaringBitmap;
 
 use crate::distance::Distance;
 use crate::node_id::NodeId;
+use crate::roaring::SerializedBitmap;
 use crate::unaligned_vector::UnalignedVector;
 use crate::ItemId;
 
@@ -38,7 +39,7 @@ impl<'a, D: Distance> Node<'a, D> {
 #[derive(Clone, Debug)]
 pub enum GenericReadNode<'a, D: Distance> {
     Leaf(Leaf<'a, D>),
-    Descendants(Descendants<'a>),
+    Descendants(GenericReadDescendants<'a>),
     SplitPlaneNormal(GenericReadSplitPlaneNormal<'a, D>),
 }
 
@@ -106,6 +107,18 @@ impl fmt::Debug for Descendants<'_> {
     }
 }
 
+/// The descendants read directly from the database, without decoding the bitmap.
+#[derive(Clone)]
+pub struct GenericReadDescendants<'a> {
+    pub descendants: SerializedBitmap<'a>,
+}
+
+impl fmt::Debug for GenericReadDescendants<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Descendants").field("descendants", &self.descendants).finish()
+    }
+}
+
 #[derive(Clone)]
 pub struct ItemIds<'a> {
     bytes: &'a [u8],
@@ -310,8 +323,8 @@ impl<'a, D: Distance> BytesDecode<'a> for GenericReadNodeCodecFromV0_4_0<D> {
                 };
                 Ok(GenericReadNode::SplitPlaneNormal(GenericReadSplitPlaneNormal { normal, left, right }))
             }
-            [DESCENDANTS_TAG, bytes @ ..] => Ok(GenericReadNode::Descendants(Descendants {
-                descendants: Cow::Owned(RoaringBitmap::deserialize_from(bytes)?),
+            [DESCENDANTS_TAG, bytes @ ..] => Ok(GenericReadNode::Descendants(GenericReadDescendants {
+                descendants: SerializedBitmap::new(bytes)?,
             })),
             unknown => panic!("Did not recognize node tag type: {unknown:?} while decoding a generic read node from v0.4.0"),
         }
@@ -325,6 +338,13 @@ impl<'a, D: Distance> BytesDecode<'a> for GenericReadNodeCodecFromV0_7_0<D> {
     type DItem = GenericReadNode<'a, D>;
 
     fn bytes_decode(bytes: &'a [u8]) -> Result<Self::DItem, BoxedError> {
+        // We don't want to decode the descendants into an owned bitmap while searching.
+        if let [DESCENDANTS_TAG, bytes @ ..] = bytes {
+            return Ok(GenericReadNode::Descendants(GenericReadDescendants {
+                descendants: SerializedBitmap::new(bytes)?,
+            }));
+        }
+
         NodeCodec::bytes_decode(bytes).map(|node| match node {
             Node::SplitPlaneNormal(split_plane_normal) => {
                 GenericReadNode::SplitPlaneNormal(GenericReadSplitPlaneNormal {
@@ -334,7 +354,7 @@ impl<'a, D: Distance> BytesDecode<'a> for GenericReadNodeCodecFromV0_7_0<D> {
                     normal: split_plane_normal.normal,
                 })
             }
-            Node::Descendants(descendants) => GenericReadNode::Descendants(descendants),
+            Node::Descendants(_) => unreachable!("The descendants are decoded above"),
             Node::Leaf(leaf) => GenericReadNode::Leaf(leaf),
         })
     }
@@ -355,10 +375,10 @@ impl<'a, D: Distance> BytesEncode<'a> for WriteNodeCodecForV0_5_0<D> {
                 Ok(NodeCodec::bytes_encode(&Node::Leaf(leaf.clone()))?.into_owned().into())
             }
             // The descendants didn't change between v0.4.0 and today.
-            GenericReadNode::Descendants(descendants) => {
-                Ok(NodeCodec::bytes_encode(&Node::<D>::Descendants(descendants.clone()))?
-                    .into_owned()
-                    .into())
+            GenericReadNode::Descendants(GenericReadDescendants { descendants }) => {
+                let mut bytes = vec![DESCENDANTS_TAG];
+                bytes.extend_from_slice(descendants.as_bytes());
+                Ok(Cow::Owned(bytes))
             }
             GenericReadNode::SplitPlaneNormal(GenericReadSplitPlaneNormal {
                 left,
@@ -235,6 +235,18 @@ impl ConcurrentNodeIds {
         }
     }
 
+    /// Creates an ID generator returning the IDs after the highest used one, without reusing the free IDs.
+    pub fn after(last_used: Option<u32>) -> ConcurrentNodeIds {
+        let current = last_used.map_or(0, |id| id + 1);
+        ConcurrentNodeIds {
+            current: AtomicU32::new(current),
+            used: AtomicU64::new(current as u64),
+            select_in_bitmap: AtomicU32::new(0),
+            look_into_bitmap: AtomicBool::new(false),
+            available: RoaringBitmap::new(),
+        }
+    }
+
     /// Returns a new unique ID and increase the count of IDs used.
     pub fn next(&self) -> Result<u32> {
         if self.used.fetch_add(1, Ordering::Relaxed) > u32::MAX as u64 {
@@ -405,6 +417,11 @@ impl<'t, D: Distance> ImmutableTrees<'t, D> {
         Ok(ImmutableTrees { trees, _marker: marker::PhantomData })
     }
 
+    /// Creates an empty structure for the operations that never read the existing tree nodes.
+    pub fn empty() -> Self {
+        ImmutableTrees { trees: IntMap::default(), _marker: marker::PhantomData }
+    }
+
     /// Returns the tree node identified by the given ID.
     pub fn get(&self, item_id: ItemId) -> heed::Result<Option<Node<'t, D>>> {
         let (ptr, len) = match self.trees.get(&item_id) {
@@ -1,25 +1,32 @@
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
 use std::collections::BinaryHeap;
-use std::iter::repeat;
 use std::marker;
 use std::num::NonZeroUsize;
+use std::sync::OnceLock;
+use std::time::Instant;
 
-use heed::types::DecodeIgnore;
-use heed::RoTxn;
+use heed::types::{Bytes, DecodeIgnore};
+use heed::{BytesDecode, RoTxn};
+use nohash::{IntMap, IntSet};
 use ordered_float::OrderedFloat;
+use rayon::prelude::*;
 use roaring::RoaringBitmap;
 
 use crate::distance::Distance;
 use crate::internals::{KeyCodec, Side};
 use crate::item_iter::ItemIter;
 use crate::node::{
-    Descendants, GenericReadNode, GenericReadNodeCodecFromV0_4_0, GenericReadNodeCodecFromV0_7_0,
-    GenericReadSplitPlaneNormal, ItemIds, Leaf,
+    GenericReadDescendants, GenericReadNode, GenericReadNodeCodecFromV0_4_0,
+    GenericReadNodeCodecFromV0_7_0, GenericReadSplitPlaneNormal, ItemIds, Leaf,
 };
 use crate::unaligned_vector::UnalignedVector;
 use crate::version::{Version, VersionCodec};
+use crate::writer::{pending_updates, PendingUpdates};
 use crate::{
-    Database, Error, ItemId, Key, MetadataCodec, Node, NodeId, Prefix, PrefixCodec, Result, Stats,
-    TreeStats,
+    Database, Error, ItemId, Key, MetadataCodec, Node, NodeId, NodeMode, Prefix, PrefixCodec,
+    QueryTrace, Result, Stats, TreeStats,
 };
 
 /// Options used to make a query against an arroy [`Reader`].
@@ -28,86 +35,878 @@ pub struct QueryBuilder<'a, D: Distance> {
     count: usize,
     search_k: Option<NonZeroUsize>,
     oversampling: Option<NonZeroUsize>,
+    trees: Option<&'a [ItemId]>,
+    max_trees: Option<NonZeroUsize>,
     candidates: Option<&'a RoaringBitmap>,
+    excluded: Option<&'a RoaringBitmap>,
+    predicate: Option<&'a dyn Fn(ItemId) -> bool>,
+    max_distance: Option<f32>,
+    similarity: bool,
+    exhaustive: bool,
+    exhaustive_ratio: Option<f64>,
+    strategy: SearchStrategy,
+    max_search_k: Option<NonZeroUsize>,
+    exclude_query_item: bool,
+    diversity: Option<(f32, NonZeroUsize)>,
+    collapse: Option<(&'a dyn Fn(ItemId) -> u64, NonZeroUsize)>,
+    deadline: Option<Instant>,
+    cancel: Option<&'a (dyn Fn() -> bool + Sync + Send)>,
+}
+
+/// The strategy used by arroy to retrieve the nearest neighbors of a query.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SearchStrategy {
+    /// Explores the trees to find the candidates to score.
+    #[default]
+    Trees,
+    /// Scores all the items, or only the candidates if specified.
+    Exhaustive,
 }
 
 impl<'a, D: Distance> QueryBuilder<'a, D> {
     /// Returns the closests items from `item`.
     ///
-    /// See also [`Self::by_vector`].
+    /// See also [`Self::by_vector`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// reader.nns(20).by_item(&rtxn, 5);
+    /// ```
+    pub fn by_item(&self, rtxn: &RoTxn, item: ItemId) -> Result<Option<Vec<(ItemId, f32)>>> {
+        match item_leaf(self.reader.database, self.reader.index, rtxn, item)? {
+            Some(leaf) => {
+                let trace = &mut QueryTrace::default();
+                let excluded = self.excluded_query_item(item);
+                let excluded_items = excluded.as_slice();
+                self.reader.nns_by_leaf(rtxn, excluded_items, &leaf, self, trace, None).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the closest items from the provided `vector`.
+    ///
+    /// See also [`Self::by_item`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// reader.nns(20).by_vector(&rtxn, &[1.25854, -0.75598, 0.58524]);
+    /// ```
+    pub fn by_vector(&self, rtxn: &RoTxn, vector: &'a [f32]) -> Result<Vec<(ItemId, f32)>> {
+        let leaf = self.query_leaf(vector)?;
+        self.reader.nns_by_leaf(rtxn, &[], &leaf, self, &mut QueryTrace::default(), None)
+    }
+
+    /// Writes the closest items from the provided `vector` into `output`, which is cleared first.
+    ///
+    /// The `scratch` holds the buffers used while exploring the trees and scoring the items.
+    /// Reusing the same scratch and output across queries avoids any allocation once they have
+    /// grown enough. This doesn't hold when the results are [diversified](Self::diversify) or
+    /// [collapsed](Self::collapse), when the query [ensures a count](Self::ensure_count),
+    /// [excludes](Self::exclude) items, [filters](Self::filter) them with a predicate or can be
+    /// interrupted by a [deadline](Self::deadline) or a [cancellation](Self::cancel), with the
+    /// binary quantized distances, or when a [stale reader](Reader::open_stale) has pending
+    /// updates.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{QueryScratch, Reader, distances::Euclidean};
+    /// # let (reader, rtxn, queries): (Reader<Euclidean>, heed::RoTxn, Vec<Vec<f32>>) = todo!();
+    /// let mut scratch = QueryScratch::new();
+    /// let mut results = Vec::new();
+    /// let query = reader.nns(20);
+    /// for vector in &queries {
+    ///     query.by_vector_with(&rtxn, vector, &mut scratch, &mut results)?;
+    ///     println!("{results:?}");
+    /// }
+    /// # Ok::<(), arroy::Error>(())
+    /// ```
+    pub fn by_vector_with(
+        &self,
+        rtxn: &RoTxn,
+        vector: &[f32],
+        scratch: &mut QueryScratch,
+        output: &mut Vec<(ItemId, f32)>,
+    ) -> Result<()> {
+        let query_leaf = self.query_leaf(vector)?;
+        output.clear();
+        if self.reader.items.is_empty() {
+            return Ok(());
+        }
+
+        let query_leafs = std::slice::from_ref(&query_leaf);
+        let nodes = TxnNodes { reader: self.reader, rtxn };
+        let trace = &mut QueryTrace::default();
+        self.reader.nns_candidates(&nodes, &[], query_leafs, self, trace, scratch)?;
+        let distance = |leaf: &Leaf<D>| D::built_distance(&query_leaf, leaf);
+        self.reader.nns_top_k(&nodes, self, trace, scratch, distance, output, None)
+    }
+
+    /// Returns the closests items from `item` along with their vectors.
+    ///
+    /// The vectors are the ones used to compute the distances, no leaf is read twice.
+    /// Prefer it over calling [`Reader::item_vector`] on every result.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// reader.nns(20).by_item_with_vectors(&rtxn, 5);
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn by_item_with_vectors(
+        &self,
+        rtxn: &RoTxn,
+        item: ItemId,
+    ) -> Result<Option<Vec<(ItemId, f32, Vec<f32>)>>> {
+        match item_leaf(self.reader.database, self.reader.index, rtxn, item)? {
+            Some(leaf) => {
+                let trace = &mut QueryTrace::default();
+                let excluded = self.excluded_query_item(item);
+                let mut vectors = Vec::new();
+                let results = self.reader.nns_by_leaf(
+                    rtxn,
+                    excluded.as_slice(),
+                    &leaf,
+                    self,
+                    trace,
+                    Some(&mut vectors),
+                )?;
+                Ok(Some(with_vectors(results, vectors)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the closest items from the provided `vector` along with their vectors.
+    ///
+    /// The vectors are the ones used to compute the distances, no leaf is read twice.
+    /// Prefer it over calling [`Reader::item_vector`] on every result.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// reader.nns(20).by_vector_with_vectors(&rtxn, &[1.25854, -0.75598, 0.58524]);
+    /// ```
+    pub fn by_vector_with_vectors(
+        &self,
+        rtxn: &RoTxn,
+        vector: &'a [f32],
+    ) -> Result<Vec<(ItemId, f32, Vec<f32>)>> {
+        let leaf = self.query_leaf(vector)?;
+        let trace = &mut QueryTrace::default();
+        let mut vectors = Vec::new();
+        let results = self.reader.nns_by_leaf(rtxn, &[], &leaf, self, trace, Some(&mut vectors))?;
+        Ok(with_vectors(results, vectors))
+    }
+
+    /// Returns the closests items from `item` along with a report
+    /// of the work done to find them.
+    ///
+    /// See also [`Self::explain_by_vector`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// if let Some((results, trace)) = reader.nns(20).explain_by_item(&rtxn, 5)? {
+    ///     println!("{} nodes popped for {} results", trace.nodes_popped, results.len());
+    /// }
+    /// # Ok::<(), arroy::Error>(())
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn explain_by_item(
+        &self,
+        rtxn: &RoTxn,
+        item: ItemId,
+    ) -> Result<Option<(Vec<(ItemId, f32)>, QueryTrace)>> {
+        match item_leaf(self.reader.database, self.reader.index, rtxn, item)? {
+            Some(leaf) => {
+                let mut trace = QueryTrace::default();
+                let excluded = self.excluded_query_item(item);
+                let excluded_items = excluded.as_slice();
+                let results =
+                    self.reader.nns_by_leaf(rtxn, excluded_items, &leaf, self, &mut trace, None)?;
+                Ok(Some((results, trace)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the closest items from the provided `vector` along with a report
+    /// of the work done to find them.
+    ///
+    /// See also [`Self::explain_by_item`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// let (results, trace) = reader.nns(20).explain_by_vector(&rtxn, &[1.25854, -0.75598, 0.58524])?;
+    /// println!("{} distances computed for {} results", trace.distance_computations, results.len());
+    /// # Ok::<(), arroy::Error>(())
+    /// ```
+    pub fn explain_by_vector(
+        &self,
+        rtxn: &RoTxn,
+        vector: &'a [f32],
+    ) -> Result<(Vec<(ItemId, f32)>, QueryTrace)> {
+        let leaf = self.query_leaf(vector)?;
+        let mut trace = QueryTrace::default();
+        let results = self.reader.nns_by_leaf(rtxn, &[], &leaf, self, &mut trace, None)?;
+        Ok((results, trace))
+    }
+
+    /// Returns the closests items from every one of the provided `items`.
+    /// The results are returned in the same order as the `items`, an item
+    /// that doesn't exist in the database is associated to `None`.
+    ///
+    /// The queries share the same options and are executed in parallel using rayon.
+    /// It can be configured by using the [`rayon::ThreadPoolBuilder`] and the
+    /// [`rayon::ThreadPool::install`]. The tree nodes are read once and shared between
+    /// the queries: they are fetched as the queries reach them, or all at once when the
+    /// queries are expected to collect more candidates than there are items in the index.
+    /// The closures of [`Self::filter`] and [`Self::collapse`] are not shared between threads,
+    /// when one of them is specified the queries are executed one after the other.
+    ///
+    /// See also [`Self::by_vectors`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// reader.nns(20).by_items(&rtxn, &[5, 12, 49]);
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn by_items(
+        &self,
+        rtxn: &RoTxn,
+        items: &[ItemId],
+    ) -> Result<Vec<Option<Vec<(ItemId, f32)>>>> {
+        let leafs = items
+            .iter()
+            .map(|&item| item_leaf(self.reader.database, self.reader.index, rtxn, item))
+            .collect::<Result<Vec<_>>>()?;
+        let found: Vec<_> = items
+            .iter()
+            .zip(&leafs)
+            .filter_map(|(&item, leaf)| leaf.clone().map(|leaf| (Some(item), leaf)))
+            .collect();
+
+        let mut results = self.reader.nns_by_leafs(rtxn, &found, self)?.into_iter();
+        Ok(leafs.iter().map(|leaf| leaf.as_ref().and_then(|_| results.next())).collect())
+    }
+
+    /// Returns the closest items from every one of the provided `vectors`.
+    /// The results are returned in the same order as the `vectors`.
+    ///
+    /// The queries share the same options and are executed in parallel using rayon.
+    /// It can be configured by using the [`rayon::ThreadPoolBuilder`] and the
+    /// [`rayon::ThreadPool::install`]. The tree nodes are read once and shared between
+    /// the queries: they are fetched as the queries reach them, or all at once when the
+    /// queries are expected to collect more candidates than there are items in the index.
+    /// The closures of [`Self::filter`] and [`Self::collapse`] are not shared between threads,
+    /// when one of them is specified the queries are executed one after the other.
+    ///
+    /// See also [`Self::by_items`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// let vectors = [[1.25854, -0.75598, 0.58524], [0.12856, 0.36124, -0.4758]];
+    /// reader.nns(20).by_vectors(&rtxn, &vectors);
+    /// ```
+    pub fn by_vectors<V: AsRef<[f32]>>(
+        &self,
+        rtxn: &RoTxn,
+        vectors: &[V],
+    ) -> Result<Vec<Vec<(ItemId, f32)>>> {
+        let leafs = vectors
+            .iter()
+            .map(|vector| self.query_leaf(vector.as_ref()).map(|leaf| (None, leaf)))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.reader.nns_by_leafs(rtxn, &leafs, self)
+    }
+
+    /// Returns an iterator over the closests items from `item`, the closest first.
+    ///
+    /// The iterator explores the trees lazily, `search_k` nodes at a time, and keeps
+    /// its state between the calls to `next`. Iterating over more than `count` items
+    /// is cheaper than running a new query with a larger `count`. Like the other queries
+    /// the order is approximate and depends on the `search_k` and oversampling options.
+    ///
+    /// Once all the matching items have been returned the iterator stops.
+    /// Items that are not within the [`QueryBuilder::within`] radius are skipped.
+    ///
+    /// Once the [`QueryBuilder::deadline`] is reached or the query is
+    /// [cancelled](QueryBuilder::cancel), the iterator stops exploring the trees and only
+    /// returns the items it already scored. The [`QueryBuilder::ensure_count`] option is
+    /// useless as the iterator keeps exploring the trees until it finds enough items or
+    /// they are exhausted, and the [`QueryBuilder::diversify`] and [`QueryBuilder::collapse`]
+    /// options are ignored.
+    ///
+    /// See also [`Self::iter_by_vector`] and [`NnsIter::into_continuation`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// let query = reader.nns(20);
+    /// if let Some(iter) = query.iter_by_item(&rtxn, 5)? {
+    ///     for result in iter.take(60) {
+    ///         let (item, distance) = result?;
+    ///         println!("{item}: {distance}");
+    ///     }
+    /// }
+    /// # Ok::<(), arroy::Error>(())
+    /// ```
+    pub fn iter_by_item<'r>(
+        &'r self,
+        rtxn: &'r RoTxn,
+        item: ItemId,
+    ) -> Result<Option<NnsIter<'r, D>>> {
+        match item_leaf(self.reader.database, self.reader.index, rtxn, item)? {
+            Some(leaf) => NnsIter::new(self, rtxn, Some(item), leaf.into_owned()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns an iterator over the closests items from the provided `vector`, the closest first.
+    ///
+    /// See [`Self::iter_by_item`] for more details.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// let query = reader.nns(20);
+    /// let first_page: Vec<_> = query.iter_by_vector(&rtxn, &[1.25854, -0.75598, 0.58524])?.take(20).collect();
+    /// # Ok::<(), arroy::Error>(())
+    /// ```
+    pub fn iter_by_vector<'r>(&'r self, rtxn: &'r RoTxn, vector: &[f32]) -> Result<NnsIter<'r, D>> {
+        let leaf = self.query_leaf(vector)?;
+        NnsIter::new(self, rtxn, None, leaf.into_owned())
+    }
+
+    /// Resumes an iterator from the state returned by [`NnsIter::into_continuation`].
+    ///
+    /// The continuation must be used with the same query options and
+    /// a read transaction that sees the same version of the database.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// let query = reader.nns(20);
+    /// let mut iter = query.iter_by_vector(&rtxn, &[1.25854, -0.75598, 0.58524])?;
+    /// let first_page: Vec<_> = iter.by_ref().take(20).collect();
+    /// let continuation = iter.into_continuation();
+    ///
+    /// // later on...
+    /// let second_page: Vec<_> = query.resume(&rtxn, continuation).take(20).collect();
+    /// # Ok::<(), arroy::Error>(())
+    /// ```
+    pub fn resume<'r>(
+        &'r self,
+        rtxn: &'r RoTxn,
+        continuation: NnsContinuation<D>,
+    ) -> NnsIter<'r, D> {
+        NnsIter { opt: self, nodes: TxnNodes { reader: self.reader, rtxn }, state: continuation }
+    }
+
+    /// Returns the closest items from all the provided `vectors` at once, ranked by their
+    /// distances to the vectors combined with the `aggregation`.
+    ///
+    /// The trees are explored a single time, following the branches that are the most
+    /// promising for any of the vectors, instead of running one search per vector.
+    /// The items that are in between the vectors may require a larger `search_k` to be
+    /// found when using the [`Aggregation::Mean`] or [`Aggregation::WeightedSum`].
+    ///
+    /// See also [`Self::by_items_aggregated`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Aggregation, Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// let vectors = [[1.25854, -0.75598, 0.58524], [0.12856, 0.36124, -0.4758]];
+    /// reader.nns(20).by_vectors_aggregated(&rtxn, &vectors, Aggregation::WeightedSum(&[0.7, 0.3]));
+    /// ```
+    pub fn by_vectors_aggregated<V: AsRef<[f32]>>(
+        &self,
+        rtxn: &RoTxn,
+        vectors: &[V],
+        aggregation: Aggregation,
+    ) -> Result<Vec<(ItemId, f32)>> {
+        aggregation.check(vectors.len())?;
+
+        let leafs = vectors
+            .iter()
+            .map(|vector| self.query_leaf(vector.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.reader.nns_by_aggregated_leafs(rtxn, &[], &leafs, aggregation, self)
+    }
+
+    /// Returns the closest items from all the provided `items` at once, ranked by their
+    /// distances to the items combined with the `aggregation`.
+    ///
+    /// The items that don't exist in the database are ignored, along with their weight.
+    /// Use [`Self::exclude_query_item`] to remove the `items` themselves from the results.
+    ///
+    /// See also [`Self::by_vectors_aggregated`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Aggregation, Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// let last_liked = [5, 12, 49, 50, 61];
+    /// reader.nns(20).exclude_query_item().by_items_aggregated(&rtxn, &last_liked, Aggregation::Mean);
+    /// ```
+    pub fn by_items_aggregated(
+        &self,
+        rtxn: &RoTxn,
+        items: &[ItemId],
+        aggregation: Aggregation,
+    ) -> Result<Vec<(ItemId, f32)>> {
+        aggregation.check(items.len())?;
+
+        let mut found = Vec::with_capacity(items.len());
+        let mut leafs = Vec::with_capacity(items.len());
+        let mut weights = Vec::new();
+        for (i, &item) in items.iter().enumerate() {
+            if let Some(leaf) = item_leaf(self.reader.database, self.reader.index, rtxn, item)? {
+                found.push(item);
+                leafs.push(leaf);
+                if let Aggregation::WeightedSum(w) = aggregation {
+                    weights.push(w[i]);
+                }
+            }
+        }
+
+        let aggregation = match aggregation {
+            Aggregation::WeightedSum(_) => Aggregation::WeightedSum(&weights),
+            aggregation => aggregation,
+        };
+        // The excluded items are sorted once to be searched while exploring the trees.
+        let mut excluded_items = Vec::new();
+        if self.exclude_query_item {
+            excluded_items.extend_from_slice(&found);
+            excluded_items.sort_unstable();
+            excluded_items.dedup();
+        }
+        self.reader.nns_by_aggregated_leafs(rtxn, &excluded_items, &leafs, aggregation, self)
+    }
+
+    /// Returns the closest items from a query built from `positives` and `negatives` examples,
+    /// Rocchio-style. The query vector is the mean of the positive items minus the mean of the
+    /// negative items multiplied by the `negative_weight`. The examples themselves are never
+    /// returned and the ones that don't exist in the database are ignored.
+    ///
+    /// The vectors are combined following the convention of the distance, e.g. they are
+    /// normalized for the cosine distance so that every example has the same influence.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// let liked = [5, 12, 49];
+    /// let disliked = [23, 7];
+    /// reader.nns(20).by_examples(&rtxn, &liked, &disliked, 0.5);
+    /// ```
+    pub fn by_examples(
+        &self,
+        rtxn: &RoTxn,
+        positives: &[ItemId],
+        negatives: &[ItemId],
+        negative_weight: f32,
+    ) -> Result<Vec<(ItemId, f32)>> {
+        let mut examples = Vec::with_capacity(positives.len() + negatives.len());
+        let mut query = vec![0.0; self.reader.dimensions()];
+        for (items, weight) in [(positives, 1.0), (negatives, -negative_weight)] {
+            let mut sum = vec![0.0; self.reader.dimensions()];
+            let mut count = 0;
+            for &item in items {
+                if let Some(leaf) = item_leaf(self.reader.database, self.reader.index, rtxn, item)?
+                {
+                    sum.iter_mut().zip(D::query_vector(&leaf)).for_each(|(s, x)| *s += x);
+                    examples.push(item);
+                    count += 1;
+                }
+            }
+            if count != 0 {
+                let factor = weight / count as f32;
+                query.iter_mut().zip(sum).for_each(|(q, s)| *q += s * factor);
+            }
+        }
+
+        if examples.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // The examples are sorted once to be searched while exploring the trees.
+        examples.sort_unstable();
+        examples.dedup();
+        let vector = UnalignedVector::from_vec(query);
+        let leaf = Leaf { header: D::new_header(&vector), vector };
+        let trace = &mut QueryTrace::default();
+        self.reader.nns_by_leaf(rtxn, &examples, &leaf, self, trace, None)
+    }
+
+    /// During the query, arroy will inspect up to `search_k` nodes which defaults
+    /// to `n_trees * count` if not provided. `search_k` gives you a run-time
+    /// tradeoff between better accuracy and speed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// use std::num::NonZeroUsize;
+    /// reader.nns(20).search_k(NonZeroUsize::new(1000).unwrap()).by_item(&rtxn, 3);
+    /// ```
+    pub fn search_k(&mut self, search_k: NonZeroUsize) -> &mut Self {
+        self.search_k = Some(search_k);
+        self
+    }
+
+    /// Oversampling will multiply [`QueryBuilder::search_k`] by the specified number.
+    /// That's useful when you don't want to compute `search_k` yourself.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// use std::num::NonZeroUsize;
+    /// reader.nns(20).oversampling(NonZeroUsize::new(6).unwrap()).by_item(&rtxn, 5);
+    /// ```
+    pub fn oversampling(&mut self, oversampling: NonZeroUsize) -> &mut Self {
+        self.oversampling = Some(oversampling);
+        self
+    }
+
+    /// Keeps exploring the trees after having inspected `search_k` nodes until `count`
+    /// distinct items matching the [`QueryBuilder::candidates`] are found, the trees are
+    /// exhausted, or `max_search_k` nodes have been inspected.
+    ///
+    /// Without it, a restrictive filter or a small `search_k` may return fewer than
+    /// `count` items even though there are enough matching items in the index.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// use std::num::NonZeroUsize;
+    /// let candidates = roaring::RoaringBitmap::from_iter([1, 3, 4, 5, 6, 7, 8, 9, 15, 16]);
+    /// let max_search_k = NonZeroUsize::new(10_000).unwrap();
+    /// reader.nns(20).candidates(&candidates).ensure_count(max_search_k).by_item(&rtxn, 6);
+    /// ```
+    pub fn ensure_count(&mut self, max_search_k: NonZeroUsize) -> &mut Self {
+        self.max_search_k = Some(max_search_k);
+        self
+    }
+
+    /// Removes the queried items from the results of [`QueryBuilder::by_item`],
+    /// [`QueryBuilder::by_items`] and [`QueryBuilder::by_items_aggregated`]. The item is
+    /// skipped while exploring the trees and the query still returns up to `count` other items.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// reader.nns(20).exclude_query_item().by_item(&rtxn, 5);
+    /// ```
+    pub fn exclude_query_item(&mut self) -> &mut Self {
+        self.exclude_query_item = true;
+        self
+    }
+
+    /// Only explores the trees whose root is in `roots`, a subset of [`Reader::roots`].
+    /// The ids that are not the root of a tree are ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// let roots: Vec<_> = reader.roots().step_by(2).collect();
+    /// reader.nns(20).trees(&roots).by_item(&rtxn, 6);
+    /// ```
+    pub fn trees(&mut self, roots: &'a [ItemId]) -> &mut Self {
+        self.trees = Some(roots);
+        self
+    }
+
+    /// Only explores the first `n_trees` trees of the index, or of the [`QueryBuilder::trees`]
+    /// if specified. It trades recall for latency without rebuilding the index, the default
+    /// `search_k` decreases accordingly.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// use std::num::NonZeroUsize;
+    /// reader.nns(5).max_trees(NonZeroUsize::new(3).unwrap()).by_item(&rtxn, 6);
+    /// ```
+    pub fn max_trees(&mut self, n_trees: NonZeroUsize) -> &mut Self {
+        self.max_trees = Some(n_trees);
+        self
+    }
+
+    /// Specify a subset of candidates to inspect. Filters out everything else.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// let candidates = roaring::RoaringBitmap::from_iter([1, 3, 4, 5, 6, 7, 8, 9, 15, 16]);
+    /// reader.nns(20).candidates(&candidates).by_item(&rtxn, 6);
+    /// ```
+    pub fn candidates(&mut self, candidates: &'a RoaringBitmap) -> &mut Self {
+        self.candidates = Some(candidates);
+        self.update_strategy();
+        self
+    }
+
+    /// Specify a subset of items to ignore. It's removed from the items of the trees nodes
+    /// while exploring them and can be combined with the [`QueryBuilder::candidates`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// let already_seen = roaring::RoaringBitmap::from_iter([1, 3, 4, 5, 6, 7, 8, 9, 15, 16]);
+    /// reader.nns(20).exclude(&already_seen).by_item(&rtxn, 6);
+    /// ```
+    pub fn exclude(&mut self, excluded: &'a RoaringBitmap) -> &mut Self {
+        self.excluded = Some(excluded);
+        self
+    }
+
+    /// Provide a closure that decides whether an item can be returned or not.
+    /// It's lazily called on the items encountered while exploring the trees, after
+    /// the [`QueryBuilder::candidates`] and [`QueryBuilder::exclude`] filters, and
+    /// at most once per item and query even if the item is found in many trees.
+    ///
+    /// The closure is only called from the thread executing the query, it can read another
+    /// database with the same read transaction. [`QueryBuilder::by_items`] and
+    /// [`QueryBuilder::by_vectors`] therefore execute their queries one after the other.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// reader.nns(20).filter(&|item| item % 2 == 0).by_item(&rtxn, 6);
+    /// ```
+    pub fn filter<F>(&mut self, predicate: &'a F) -> &mut Self
+    where
+        F: Fn(ItemId) -> bool,
+    {
+        self.predicate = Some(predicate);
+        self
+    }
+
+    /// Only returns the items that are within `max_distance` from the query, the number of
+    /// results is still capped by the `count` given to [`Reader::nns`]. Use a large `count` to
+    /// retrieve all the items closer than the threshold.
+    ///
+    /// With the [`Euclidean`](crate::distances::Euclidean) and [`Manhattan`](crate::distances::Manhattan)
+    /// distances, the branches of the trees that are out of the radius are skipped and, unless a
+    /// `search_k` is specified, all the branches within the radius are explored. With the other
+    /// distances, the databases built before v0.7.0, or the aggregated queries, the default
+    /// `search_k` grows with the `count` and you may want to specify it to bound the number of
+    /// nodes arroy will explore.
+    ///
+    /// The `max_distance` is expressed in the same unit as the distances returned by the queries.
+    /// Keep in mind that the [`DotProduct`](crate::distances::DotProduct) returns the dot product
+    /// itself, and therefore only returns the items with a dot product higher than the threshold.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// reader.nns(usize::MAX).within(0.25).by_item(&rtxn, 6);
+    /// ```
+    pub fn within(&mut self, max_distance: f32) -> &mut Self {
+        self.max_distance = Some(max_distance);
+        self
+    }
+
+    /// Returns the [`Distance::similarity`] of the items instead of their distance, the higher
+    /// the closer. For example the cosine similarity in `[-1, 1]` with [`Cosine`](crate::distances::Cosine)
+    /// or the raw dot product with [`DotProduct`](crate::distances::DotProduct).
+    /// The results are still sorted from the closest to the farthest and
+    /// [`QueryBuilder::within`] still expects a distance.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Cosine};
+    /// # let (reader, rtxn): (Reader<Cosine>, heed::RoTxn) = todo!();
+    /// reader.nns(20).similarity().by_item(&rtxn, 6);
+    /// ```
+    pub fn similarity(&mut self) -> &mut Self {
+        self.similarity = true;
+        self
+    }
+
+    /// Diversifies the results with the maximal marginal relevance algorithm. The `count`
+    /// results are picked one by one among the `pool_size` nearest items, trading their
+    /// distance to the query against their distance to the items already picked.
+    ///
+    /// The `lambda` must be between `0.0` and `1.0`, `1.0` ranks the items by their distance to
+    /// the query only while `0.0` only cares about the diversity. The results are returned in the
+    /// order they were picked. The default `search_k` grows with the `pool_size`.
+    /// The iterators ignore this option.
     ///
     /// # Examples
     ///
     /// ```no_run
     /// # use arroy::{Reader, distances::Euclidean};
     /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
-    /// reader.nns(20).by_item(&rtxn, 5);
+    /// use std::num::NonZeroUsize;
+    /// reader.nns(20).diversify(0.7, NonZeroUsize::new(100).unwrap()).by_item(&rtxn, 5);
     /// ```
-    pub fn by_item(&self, rtxn: &RoTxn, item: ItemId) -> Result<Option<Vec<(ItemId, f32)>>> {
-        match item_leaf(self.reader.database, self.reader.index, rtxn, item)? {
-            Some(leaf) => self.reader.nns_by_leaf(rtxn, &leaf, self).map(Some),
-            None => Ok(None),
-        }
+    pub fn diversify(&mut self, lambda: f32, pool_size: NonZeroUsize) -> &mut Self {
+        self.diversity = Some((lambda, pool_size));
+        self
     }
 
-    /// Returns the closest items from the provided `vector`.
+    /// Collapses the results by group, the `group` closure returns the group of an item, e.g.,
+    /// the document a chunk comes from. The query returns the `per_group` nearest items of the
+    /// `count` nearest groups, sorted by distance.
     ///
-    /// See also [`Self::by_item`].
+    /// The trees are explored until `count` distinct groups are found, or the
+    /// [`QueryBuilder::ensure_count`] limit is reached. The results are not
+    /// [diversified](Self::diversify) and the iterators ignore this option.
+    /// Like the [`QueryBuilder::filter`] closure, the `group` closure is only
+    /// called from the thread executing the query.
     ///
     /// # Examples
     ///
     /// ```no_run
     /// # use arroy::{Reader, distances::Euclidean};
     /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
-    /// reader.nns(20).by_vector(&rtxn, &[1.25854, -0.75598, 0.58524]);
+    /// use std::collections::HashMap;
+    /// use std::num::NonZeroUsize;
+    /// let documents: HashMap<u32, u64> = HashMap::from([(1, 10), (2, 10), (3, 20)]);
+    /// let document = |item| documents.get(&item).copied().unwrap_or(u64::MAX);
+    /// reader.nns(20).collapse(&document, NonZeroUsize::new(3).unwrap()).by_item(&rtxn, 5);
     /// ```
-    pub fn by_vector(&self, rtxn: &RoTxn, vector: &'a [f32]) -> Result<Vec<(ItemId, f32)>> {
-        if vector.len() != self.reader.dimensions() {
-            return Err(Error::InvalidVecDimension {
-                expected: self.reader.dimensions(),
-                received: vector.len(),
-            });
-        }
+    pub fn collapse<F>(&mut self, group: &'a F, per_group: NonZeroUsize) -> &mut Self
+    where
+        F: Fn(ItemId) -> u64,
+    {
+        self.collapse = Some((group, per_group));
+        self
+    }
 
-        let vector = UnalignedVector::from_slice(vector);
-        let leaf = Leaf { header: D::new_header(&vector), vector };
-        self.reader.nns_by_leaf(rtxn, &leaf, self)
+    /// Stops exploring the trees and scoring the items once the `deadline` is reached.
+    /// The query then returns the best results among the items scored so far, if any, and
+    /// the [`QueryTrace::partial`] flag is set. The flag is only reported by the
+    /// [`QueryBuilder::explain_by_item`] and [`QueryBuilder::explain_by_vector`] methods.
+    ///
+    /// All the items found in the trees before the deadline are scored. Once the trees are
+    /// explored in time, the items are scored until the deadline, but the first ones found,
+    /// `count` times the oversampling, are always scored. The exhaustive search always scores
+    /// as many items, the ones with the lowest ids.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// use std::time::{Duration, Instant};
+    /// let deadline = Instant::now() + Duration::from_millis(20);
+    /// let (results, trace) = reader.nns(20).deadline(deadline).explain_by_item(&rtxn, 5)?.unwrap();
+    /// if trace.partial {
+    ///     println!("only {} results found in time", results.len());
+    /// }
+    /// # Ok::<(), arroy::Error>(())
+    /// ```
+    pub fn deadline(&mut self, deadline: Instant) -> &mut Self {
+        self.deadline = Some(deadline);
+        self
     }
 
-    /// During the query, arroy will inspect up to `search_k` nodes which defaults
-    /// to `n_trees * count` if not provided. `search_k` gives you a run-time
-    /// tradeoff between better accuracy and speed.
+    /// Provide a closure that can stop the query early if needed. It's regularly called
+    /// while exploring the trees and scoring the items. Once it returns `true` the query
+    /// returns the best results among the items scored so far, if any, and the
+    /// [`QueryTrace::partial`] flag is set. The flag is only reported by the
+    /// [`QueryBuilder::explain_by_item`] and [`QueryBuilder::explain_by_vector`] methods.
+    /// Like with a [`QueryBuilder::deadline`], the first items found are always scored.
     ///
     /// # Examples
     ///
     /// ```no_run
     /// # use arroy::{Reader, distances::Euclidean};
     /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
-    /// use std::num::NonZeroUsize;
-    /// reader.nns(20).search_k(NonZeroUsize::new(1000).unwrap()).by_item(&rtxn, 3);
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// let stop = AtomicBool::new(false);
+    /// reader.nns(20).cancel(&|| stop.load(Ordering::Relaxed)).by_item(&rtxn, 5);
     /// ```
-    pub fn search_k(&mut self, search_k: NonZeroUsize) -> &mut Self {
-        self.search_k = Some(search_k);
+    pub fn cancel<F>(&mut self, cancel: &'a F) -> &mut Self
+    where
+        F: Fn() -> bool + Sync + Send,
+    {
+        self.cancel = Some(cancel);
         self
     }
 
-    /// Oversampling will multiply [`QueryBuilder::search_k`] by the specified number.
-    /// That's useful when you don't want to compute `search_k` yourself.
+    /// Bypasses the trees and computes the distance between the query and every item,
+    /// or only the [`QueryBuilder::candidates`] if specified. The results are exact
+    /// which makes it useful to compute the ground truth of a query or when the
+    /// candidates only contain a handful of items. When most of the items are scored,
+    /// their vectors are read with a single walk over the database.
     ///
     /// # Examples
     ///
     /// ```no_run
     /// # use arroy::{Reader, distances::Euclidean};
     /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
-    /// use std::num::NonZeroUsize;
-    /// reader.nns(20).oversampling(NonZeroUsize::new(6).unwrap()).by_item(&rtxn, 5);
+    /// reader.nns(20).exhaustive().by_item(&rtxn, 6);
     /// ```
-    pub fn oversampling(&mut self, oversampling: NonZeroUsize) -> &mut Self {
-        self.oversampling = Some(oversampling);
+    pub fn exhaustive(&mut self) -> &mut Self {
+        self.exhaustive = true;
+        self.update_strategy();
         self
     }
 
-    /// Specify a subset of candidates to inspect. Filters out everything else.
+    /// Automatically switches to an [exhaustive](Self::exhaustive) search when the
+    /// [`QueryBuilder::candidates`] represent less than `ratio` of the items of the index.
+    /// With very selective candidates most of the items found in the trees are filtered
+    /// out and scoring the candidates directly is both faster and exact.
+    ///
+    /// Use [`QueryBuilder::strategy`] to know which strategy is picked.
     ///
     /// # Examples
     ///
@@ -115,12 +914,530 @@ impl<'a, D: Distance> QueryBuilder<'a, D> {
     /// # use arroy::{Reader, distances::Euclidean};
     /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
     /// let candidates = roaring::RoaringBitmap::from_iter([1, 3, 4, 5, 6, 7, 8, 9, 15, 16]);
-    /// reader.nns(20).candidates(&candidates).by_item(&rtxn, 6);
+    /// reader.nns(20).candidates(&candidates).exhaustive_below(0.01).by_item(&rtxn, 6);
     /// ```
-    pub fn candidates(&mut self, candidates: &'a RoaringBitmap) -> &mut Self {
-        self.candidates = Some(candidates);
+    pub fn exhaustive_below(&mut self, ratio: f64) -> &mut Self {
+        self.exhaustive_ratio = Some(ratio);
+        self.update_strategy();
+        self
+    }
+
+    /// Returns the strategy that will be used to execute the queries
+    /// with the current options.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, SearchStrategy, distances::Euclidean};
+    /// # let (reader, rtxn): (Reader<Euclidean>, heed::RoTxn) = todo!();
+    /// let candidates = roaring::RoaringBitmap::from_iter([1, 3, 4, 5, 6, 7, 8, 9, 15, 16]);
+    /// let mut query = reader.nns(20);
+    /// query.candidates(&candidates).exhaustive_below(0.01);
+    /// if query.strategy() == SearchStrategy::Exhaustive {
+    ///     println!("scoring the candidates directly");
+    /// }
+    /// ```
+    pub fn strategy(&self) -> SearchStrategy {
+        self.strategy
+    }
+
+    /// Picks the strategy once the options it depends on are set, so that the
+    /// candidates are counted once instead of every time a query is executed.
+    fn update_strategy(&mut self) {
+        self.strategy = match (self.exhaustive, self.candidates, self.exhaustive_ratio) {
+            (true, ..) => SearchStrategy::Exhaustive,
+            (false, Some(candidates), Some(ratio)) => {
+                let selected = candidates.intersection_len(&self.reader.items) as f64;
+                if selected < ratio * self.reader.n_items() as f64 {
+                    SearchStrategy::Exhaustive
+                } else {
+                    SearchStrategy::Trees
+                }
+            }
+            (false, ..) => SearchStrategy::Trees,
+        };
+    }
+
+    /// Returns the leaf of the query `vector` after checking its dimensions.
+    fn query_leaf<'v>(&self, vector: &'v [f32]) -> Result<Leaf<'v, D>> {
+        if vector.len() != self.reader.dimensions() {
+            return Err(Error::InvalidVecDimension {
+                expected: self.reader.dimensions(),
+                received: vector.len(),
+            });
+        }
+
+        let vector = UnalignedVector::from_slice(vector);
+        Ok(Leaf { header: D::new_header(&vector), vector })
+    }
+
+    /// Returns the query item if it must be excluded from the results.
+    fn excluded_query_item(&self, query_item: ItemId) -> Option<ItemId> {
+        Some(query_item).filter(|_| self.exclude_query_item)
+    }
+
+    /// Returns the normalized `distance` or the similarity that must be returned to the user.
+    fn score(&self, distance: f32) -> f32 {
+        if self.similarity {
+            D::similarity(distance, self.reader.dimensions)
+        } else {
+            distance
+        }
+    }
+
+    /// Returns `true` if the deadline is reached or the query was cancelled.
+    fn interrupted(&self) -> bool {
+        self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            || self.cancel.is_some_and(|cancel| cancel())
+    }
+
+    /// Returns the roots of the trees to explore.
+    fn roots(&self) -> impl Iterator<Item = ItemId> + 'a {
+        let trees = self.trees;
+        let max_trees = self.max_trees.map_or(usize::MAX, NonZeroUsize::get);
+        self.reader
+            .roots
+            .iter()
+            .filter(move |root| trees.is_none_or(|trees| trees.contains(root)))
+            .take(max_trees)
+    }
+
+    /// Returns the radius out of which the branches of the trees can be skipped, if any,
+    /// when exploring them for `n_queries` queries at once.
+    fn pruning_radius(&self, n_queries: usize) -> Option<f32> {
+        // The split planes only store their offset since v0.7.0.
+        let biased = !matches!(self.reader.version, Version { major: 0, minor: 0..=6, patch: _ });
+        // The results are ranked by their aggregated distance when there are many queries.
+        let single = n_queries == 1;
+        self.max_distance.filter(|_| D::MARGIN_BOUNDS_DISTANCE && biased && single)
+    }
+
+    /// Returns the number of items to collect from the trees for `n_queries` queries explored
+    /// at once, after the oversampling.
+    fn effective_search_k(&self, n_queries: usize) -> usize {
+        // The radius bounds the exploration by itself.
+        if self.search_k.is_none() && self.pruning_radius(n_queries).is_some() {
+            return usize::MAX;
+        }
+
+        let n_trees = self.roots().count();
+        let count = self.pool_count();
+        let search_k = self.search_k.map_or(count.saturating_mul(n_trees), NonZeroUsize::get);
+        self.oversampled(search_k)
+    }
+
+    /// Returns the number of items the query must score before ranking them, including the
+    /// pool of items to diversify and the items of every group.
+    fn pool_count(&self) -> usize {
+        let count =
+            self.diversity.map_or(self.count, |(_, pool_size)| pool_size.get().max(self.count));
+        match self.collapse {
+            Some((_, per_group)) => count.saturating_mul(per_group.get()),
+            None => count,
+        }
+    }
+
+    /// Returns the number of items multiplied by the oversampling.
+    fn oversampled(&self, n_items: usize) -> usize {
+        let oversampling = self.oversampling.map_or(D::DEFAULT_OVERSAMPLING, NonZeroUsize::get);
+        n_items.saturating_mul(oversampling)
+    }
+
+    /// Returns the number of candidates, the first ones found while exploring the trees, that are
+    /// scored even if the query is interrupted. As many as a single tree contributes by default.
+    fn promising_count(&self) -> usize {
+        match (self.deadline, self.cancel) {
+            (None, None) => 0,
+            _ => self.oversampled(self.pool_count()),
+        }
+    }
+
+    /// Returns `true` if the `item` can be returned by the query and is not one of the sorted
+    /// `excluded_items`. The [excluded](Self::exclude) bitmap must have been removed beforehand.
+    fn accepts(&self, excluded_items: &[ItemId], memo: &mut PredicateMemo, item: ItemId) -> bool {
+        self.candidates.is_none_or(|c| c.contains(item))
+            && excluded_items.binary_search(&item).is_err()
+            && !self.reader.pending.deleted.contains(item)
+            && self.predicate.is_none_or(|predicate| memo.accepts(predicate, item))
+    }
+
+    /// Removes the items that can't be returned by the query, including the `excluded_items`.
+    /// The decisions of the predicate are remembered in the `memo`.
+    fn filter_items<'b>(
+        &self,
+        excluded_items: &[ItemId],
+        memo: &mut PredicateMemo,
+        items: &'b RoaringBitmap,
+    ) -> Cow<'b, RoaringBitmap> {
+        let mut items = match self.candidates {
+            Some(candidates) => Cow::Owned(items & candidates),
+            None => Cow::Borrowed(items),
+        };
+        if let Some(excluded) = self.excluded {
+            match &mut items {
+                Cow::Owned(items) => *items -= excluded,
+                Cow::Borrowed(borrowed) => items = Cow::Owned(&**borrowed - excluded),
+            }
+        }
+        for &excluded in excluded_items {
+            if items.contains(excluded) {
+                items.to_mut().remove(excluded);
+            }
+        }
+        // The trees of a stale reader may still reference the deleted items.
+        let deleted = &self.reader.pending.deleted;
+        if !deleted.is_empty() && !items.is_disjoint(deleted) {
+            *items.to_mut() -= deleted;
+        }
+        if let Some(predicate) = self.predicate {
+            items =
+                Cow::Owned(items.iter().filter(|&item| memo.accepts(predicate, item)).collect());
+        }
+        items
+    }
+}
+
+/// How the distances between an item and multiple queries are combined,
+/// see [`QueryBuilder::by_vectors_aggregated`].
+///
+/// The aggregation is applied on the distances used internally to rank the items
+/// and the result is normalized like any other distance. For the euclidean
+/// distance it means that the squared distances are aggregated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Aggregation<'w> {
+    /// The distance to the closest query.
+    Min,
+    /// The mean of the distances to the queries.
+    Mean,
+    /// The sum of the distances to the queries multiplied by their weight.
+    /// There must be one weight per query, in the same order.
+    WeightedSum(&'w [f32]),
+}
+
+impl Aggregation<'_> {
+    fn check(&self, n_queries: usize) -> Result<()> {
+        match self {
+            Aggregation::WeightedSum(weights) if weights.len() != n_queries => {
+                Err(Error::InvalidWeights { expected: n_queries, received: weights.len() })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn aggregate(&self, distances: impl Iterator<Item = f32>) -> f32 {
+        match self {
+            Aggregation::Min => distances.fold(f32::INFINITY, f32::min),
+            Aggregation::Mean => {
+                let (count, sum) = distances.fold((0, 0.0), |(c, s), d| (c + 1, s + d));
+                sum / count as f32
+            }
+            Aggregation::WeightedSum(weights) => distances.zip(*weights).map(|(d, w)| d * w).sum(),
+        }
+    }
+}
+
+/// Runs the same query against several arroy indexes, e.g., one per tenant shard or embedder,
+/// and merges the results into one ranked list.
+///
+/// Every hit is tagged with the index it comes from and ranked by its [`Distance::relevance`]
+/// multiplied by the weight of its index. The relevance is never negative, a lower weight
+/// always makes the hits of an index less relevant, and the indexes can use different distances.
+/// Each [`QueryBuilder`] keeps its own options and returns up to its own `count` results before
+/// the merge.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use arroy::{MultiQuery, Reader, distances::{Cosine, Euclidean}};
+/// # let (shard_a, shard_b, rtxn): (Reader<Euclidean>, Reader<Cosine>, heed::RoTxn) = todo!();
+/// let (query_a, query_b) = (shard_a.nns(20), shard_b.nns(20));
+/// let mut query = MultiQuery::new(20);
+/// query.add(&query_a, 1.0).add(&query_b, 0.5);
+/// for (index, item, score) in query.by_vector(&rtxn, &[1.25854, -0.75598, 0.58524])? {
+///     println!("{index}: {item} ({score})");
+/// }
+/// # Ok::<(), arroy::Error>(())
+/// ```
+pub struct MultiQuery<'q> {
+    count: usize,
+    queries: Vec<(&'q dyn IndexQuery, f32)>,
+}
+
+impl<'q> MultiQuery<'q> {
+    /// Creates an empty multi-index query that returns up to `count` results.
+    pub fn new(count: usize) -> Self {
+        MultiQuery { count, queries: Vec::new() }
+    }
+
+    /// Adds the query of an index, its results are weighted by `weight`.
+    pub fn add<D: Distance>(&mut self, query: &'q QueryBuilder<'_, D>, weight: f32) -> &mut Self {
+        self.queries.push((query, weight));
         self
     }
+
+    /// Returns the `count` best items from the provided `vector` among all the indexes,
+    /// along with their index and their weighted relevance, the best first.
+    pub fn by_vector(&self, rtxn: &RoTxn, vector: &[f32]) -> Result<Vec<(u16, ItemId, f32)>> {
+        let mut results = Vec::new();
+        for &(query, weight) in &self.queries {
+            let index = query.index();
+            let relevances = query.relevances(rtxn, vector)?;
+            results.extend(relevances.into_iter().map(|(item, rel)| (index, item, weight * rel)));
+        }
+
+        // The sort is stable, the ties are broken by the order in which the queries were added.
+        results.sort_by_key(|(_, _, score)| Reverse(OrderedFloat(*score)));
+        results.truncate(self.count);
+        Ok(results)
+    }
+}
+
+/// The query of an index of a [`MultiQuery`], whatever its distance.
+trait IndexQuery {
+    /// Returns the index the query is executed against.
+    fn index(&self) -> u16;
+
+    /// Returns the closest items from the `vector` along with their [`Distance::relevance`].
+    fn relevances(&self, rtxn: &RoTxn, vector: &[f32]) -> Result<Vec<(ItemId, f32)>>;
+}
+
+impl<D: Distance> IndexQuery for QueryBuilder<'_, D> {
+    fn index(&self) -> u16 {
+        self.reader.index()
+    }
+
+    fn relevances(&self, rtxn: &RoTxn, vector: &[f32]) -> Result<Vec<(ItemId, f32)>> {
+        let leaf = self.query_leaf(vector)?;
+        let trace = &mut QueryTrace::default();
+        let mut results = self.reader.nns_by_leaf(rtxn, &[], &leaf, self, trace, None)?;
+        let dimensions = self.reader.dimensions();
+        for (_, score) in &mut results {
+            let similarity =
+                if self.similarity { *score } else { D::similarity(*score, dimensions) };
+            *score = D::relevance(similarity);
+        }
+        Ok(results)
+    }
+}
+
+/// The buffers used while executing a query, see [`QueryBuilder::by_vector_with`].
+///
+/// Reusing the same scratch for many queries avoids allocating
+/// new buffers every time once they have grown enough.
+#[derive(Debug, Default)]
+pub struct QueryScratch {
+    /// The tree nodes to explore.
+    exploration: Exploration,
+    /// The sorted and deduplicated items to score.
+    candidates: Vec<ItemId>,
+    /// The distinct items collected, only tracked when the count must be ensured.
+    distinct: RoaringBitmap,
+    /// The distinct groups collected, only tracked when the results are collapsed.
+    groups: IntSet<u64>,
+    /// The items already accepted or rejected by the predicate.
+    predicate_memo: PredicateMemo,
+    /// The first distinct candidates found, only tracked when the query can be interrupted.
+    promising: Vec<ItemId>,
+    /// The distances of the candidates, identified by their position.
+    distances: Vec<(OrderedFloat<f32>, u32)>,
+}
+
+/// The state of an exploration of the trees for one or many queries, see [`Reader::explore_step`].
+#[derive(Debug, Default)]
+struct Exploration {
+    /// The tree nodes to explore, with their best priority and the offset of their priorities.
+    queue: BinaryHeap<(OrderedFloat<f32>, NodeId, usize)>,
+    /// The priorities of the tree nodes for every query.
+    priorities: Vec<f32>,
+    /// The margins between a split plane and every query.
+    margins: Vec<f32>,
+}
+
+impl Exploration {
+    fn clear(&mut self) {
+        self.queue.clear();
+        self.priorities.clear();
+    }
+
+    /// Queues the `roots` of the trees and associates them to the highest distance.
+    fn push_roots(&mut self, roots: impl Iterator<Item = ItemId>, n_queries: usize) {
+        // The priorities of the nodes for every query are stored contiguously, the
+        // queue only references the offset of the first one along with the best one.
+        for root in roots {
+            let offset = self.priorities.len();
+            self.priorities.resize(offset + n_queries, f32::INFINITY);
+            self.queue.push((OrderedFloat(f32::INFINITY), NodeId::tree(root), offset));
+        }
+    }
+}
+
+/// The outcome of a [`Reader::explore_step`].
+enum ExploreStep {
+    /// A tree node was explored.
+    Explored,
+    /// The next tree node to explore must be fetched first.
+    Missing(NodeId),
+    /// All the tree nodes were explored.
+    Exhausted,
+}
+
+/// The options of a query without its closures, they can be shared between the threads
+/// executing a batch of queries, see [`QueryBuilder::by_items`].
+struct SharedQuery<'a, D: Distance> {
+    reader: &'a Reader<'a, D>,
+    count: usize,
+    search_k: Option<NonZeroUsize>,
+    oversampling: Option<NonZeroUsize>,
+    trees: Option<&'a [ItemId]>,
+    max_trees: Option<NonZeroUsize>,
+    candidates: Option<&'a RoaringBitmap>,
+    excluded: Option<&'a RoaringBitmap>,
+    max_distance: Option<f32>,
+    similarity: bool,
+    exhaustive: bool,
+    exhaustive_ratio: Option<f64>,
+    strategy: SearchStrategy,
+    max_search_k: Option<NonZeroUsize>,
+    exclude_query_item: bool,
+    diversity: Option<(f32, NonZeroUsize)>,
+    deadline: Option<Instant>,
+    cancel: Option<&'a (dyn Fn() -> bool + Sync + Send)>,
+}
+
+impl<'a, D: Distance> SharedQuery<'a, D> {
+    /// Returns `None` if the query holds closures that can't be shared between threads.
+    fn new(query: &QueryBuilder<'a, D>) -> Option<Self> {
+        let QueryBuilder {
+            reader,
+            count,
+            search_k,
+            oversampling,
+            trees,
+            max_trees,
+            candidates,
+            excluded,
+            max_distance,
+            similarity,
+            exhaustive,
+            exhaustive_ratio,
+            strategy,
+            max_search_k,
+            exclude_query_item,
+            diversity,
+            deadline,
+            cancel,
+            predicate: None,
+            collapse: None,
+        } = *query
+        else {
+            return None;
+        };
+        Some(SharedQuery {
+            reader,
+            count,
+            search_k,
+            oversampling,
+            trees,
+            max_trees,
+            candidates,
+            excluded,
+            max_distance,
+            similarity,
+            exhaustive,
+            exhaustive_ratio,
+            strategy,
+            max_search_k,
+            exclude_query_item,
+            diversity,
+            deadline,
+            cancel,
+        })
+    }
+
+    /// Returns the query to execute on the current thread.
+    fn get(&self) -> QueryBuilder<'a, D> {
+        let SharedQuery {
+            reader,
+            count,
+            search_k,
+            oversampling,
+            trees,
+            max_trees,
+            candidates,
+            excluded,
+            max_distance,
+            similarity,
+            exhaustive,
+            exhaustive_ratio,
+            strategy,
+            max_search_k,
+            exclude_query_item,
+            diversity,
+            deadline,
+            cancel,
+        } = *self;
+        QueryBuilder {
+            reader,
+            count,
+            search_k,
+            oversampling,
+            trees,
+            max_trees,
+            candidates,
+            excluded,
+            max_distance,
+            similarity,
+            exhaustive,
+            exhaustive_ratio,
+            strategy,
+            max_search_k,
+            exclude_query_item,
+            diversity,
+            deadline,
+            cancel,
+            predicate: None,
+            collapse: None,
+        }
+    }
+}
+
+/// Remembers the items accepted or rejected by the [`QueryBuilder::filter`] predicate, so that
+/// it's called once per item even if the item is found in many trees.
+#[derive(Debug, Default)]
+struct PredicateMemo {
+    accepted: RoaringBitmap,
+    rejected: RoaringBitmap,
+}
+
+impl PredicateMemo {
+    fn clear(&mut self) {
+        self.accepted.clear();
+        self.rejected.clear();
+    }
+
+    /// Returns the decision of the predicate for the item, only calling it the first time.
+    fn accepts(&mut self, predicate: &dyn Fn(ItemId) -> bool, item: ItemId) -> bool {
+        if self.accepted.contains(item) {
+            true
+        } else if self.rejected.contains(item) {
+            false
+        } else {
+            let accepted = predicate(item);
+            if accepted {
+                self.accepted.insert(item);
+            } else {
+                self.rejected.insert(item);
+            }
+            accepted
+        }
+    }
+}
+
+impl QueryScratch {
+    /// Creates a new scratch with empty buffers.
+    pub fn new() -> QueryScratch {
+        QueryScratch::default()
+    }
 }
 
 /// A reader over the arroy trees and user items.
@@ -131,6 +1448,8 @@ pub struct Reader<'t, D: Distance> {
     roots: ItemIds<'t>,
     dimensions: usize,
     items: RoaringBitmap,
+    /// The changes applied on the fly when the reader was opened with [`Reader::open_stale`].
+    pending: PendingUpdates,
     version: Version,
     _marker: marker::PhantomData<D>,
 }
@@ -138,6 +1457,34 @@ pub struct Reader<'t, D: Distance> {
 impl<'t, D: Distance> Reader<'t, D> {
     /// Returns a reader over the database with the specified [`Distance`] type.
     pub fn open(rtxn: &'t RoTxn, index: u16, database: Database<D>) -> Result<Reader<'t, D>> {
+        Self::open_with(rtxn, index, database, false)
+    }
+
+    /// Returns a reader over the database even if items were updated since the last build.
+    ///
+    /// The queries are served from the trees of the last build and the pending changes
+    /// are applied on the fly: the deleted items are never returned and the added or
+    /// updated items are scored against every query, so it gets slower as the number
+    /// of pending updates grows. It can be used in the write transaction of the updates.
+    ///
+    /// ```no_run
+    /// # use arroy::{Reader, Writer, distances::Euclidean};
+    /// # let (writer, mut wtxn, database): (Writer<Euclidean>, heed::RwTxn, arroy::Database<Euclidean>) = todo!();
+    /// writer.add_item(&mut wtxn, 42, &[1.0, 2.0])?;
+    /// let reader = Reader::open_stale(&wtxn, 0, database)?;
+    /// let results = reader.nns(10).by_vector(&wtxn, &[1.0, 2.0])?;
+    /// # Ok::<(), arroy::Error>(())
+    /// ```
+    pub fn open_stale(rtxn: &'t RoTxn, index: u16, database: Database<D>) -> Result<Reader<'t, D>> {
+        Self::open_with(rtxn, index, database, true)
+    }
+
+    fn open_with(
+        rtxn: &'t RoTxn,
+        index: u16,
+        database: Database<D>,
+        stale: bool,
+    ) -> Result<Reader<'t, D>> {
         let metadata_key = Key::metadata(index);
         let metadata = match database.remap_data_type::<MetadataCodec>().get(rtxn, &metadata_key)? {
             Some(metadata) => metadata,
@@ -155,7 +1502,9 @@ impl<'t, D: Distance> Reader<'t, D> {
                 received: D::name(),
             });
         }
-        if database
+        let pending = if stale {
+            pending_updates(database, index, rtxn)?
+        } else if database
             .remap_types::<PrefixCodec, DecodeIgnore>()
             .prefix_iter(rtxn, &Prefix::updated(index))?
             .remap_key_type::<KeyCodec>()
@@ -163,14 +1512,20 @@ impl<'t, D: Distance> Reader<'t, D> {
             .is_some()
         {
             return Err(Error::NeedBuild(index));
-        }
+        } else {
+            PendingUpdates::default()
+        };
+        let mut items = metadata.items;
+        items -= &pending.deleted;
+        items |= &pending.updated;
 
         Ok(Reader {
             database: database.remap_data_type(),
             index,
             roots: metadata.roots,
             dimensions: metadata.dimensions.try_into().unwrap(),
-            items: metadata.items,
+            items,
+            pending,
             version,
             _marker: marker::PhantomData,
         })
@@ -186,6 +1541,11 @@ impl<'t, D: Distance> Reader<'t, D> {
         self.roots.len()
     }
 
+    /// Returns the ids of the root nodes of the trees, see [`QueryBuilder::trees`].
+    pub fn roots(&self) -> impl Iterator<Item = ItemId> + 't {
+        self.roots.iter()
+    }
+
     /// Returns the number of vectors stored in the index.
     pub fn n_items(&self) -> u64 {
         self.items.len()
@@ -290,114 +1650,584 @@ impl<'t, D: Distance> Reader<'t, D> {
         })
     }
 
-    /// Return a [`QueryBuilder`] that lets you configure and execute a search request.
-    ///
-    /// You must provide the number of items you want to receive.
-    pub fn nns(&self, count: usize) -> QueryBuilder<D> {
-        QueryBuilder { reader: self, count, search_k: None, oversampling: None, candidates: None }
+    /// Return a [`QueryBuilder`] that lets you configure and execute a search request.
+    ///
+    /// You must provide the number of items you want to receive.
+    pub fn nns(&self, count: usize) -> QueryBuilder<'_, D> {
+        QueryBuilder {
+            reader: self,
+            count,
+            search_k: None,
+            oversampling: None,
+            trees: None,
+            max_trees: None,
+            candidates: None,
+            excluded: None,
+            predicate: None,
+            max_distance: None,
+            similarity: false,
+            exhaustive: false,
+            exhaustive_ratio: None,
+            strategy: SearchStrategy::Trees,
+            max_search_k: None,
+            exclude_query_item: false,
+            diversity: None,
+            collapse: None,
+            deadline: None,
+            cancel: None,
+        }
+    }
+
+    /// Get a generic read node from the database using the version of the database found while creating the reader.
+    /// Must be used every time we retrieve a node in this file.
+    fn database_get(&self, rtxn: &'t RoTxn, key: &Key) -> Result<Option<GenericReadNode<'t, D>>> {
+        // The key is encoded on the stack as this is called for every node explored.
+        match self.database.remap_types::<Bytes, Bytes>().get(rtxn, &key.to_bytes())? {
+            Some(bytes) => decode_node(self.version, bytes).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the nearest items from the query leaf, except the sorted `excluded_items`.
+    fn nns_by_leaf(
+        &self,
+        rtxn: &'t RoTxn,
+        excluded_items: &[ItemId],
+        query_leaf: &Leaf<D>,
+        opt: &QueryBuilder<D>,
+        trace: &mut QueryTrace,
+        vectors: Option<&mut Vec<Vec<f32>>>,
+    ) -> Result<Vec<(ItemId, f32)>> {
+        if self.items.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let nodes = TxnNodes { reader: self, rtxn };
+        let query_leafs = std::slice::from_ref(query_leaf);
+        let scratch = &mut QueryScratch::default();
+        self.nns_candidates(&nodes, excluded_items, query_leafs, opt, trace, scratch)?;
+        let distance = |leaf: &Leaf<D>| D::built_distance(query_leaf, leaf);
+        let mut results = Vec::new();
+        self.nns_top_k(&nodes, opt, trace, scratch, distance, &mut results, vectors)?;
+        Ok(results)
+    }
+
+    /// Explores the trees once for all the query leafs and ranks the items
+    /// by their aggregated distance to the queries.
+    fn nns_by_aggregated_leafs(
+        &self,
+        rtxn: &'t RoTxn,
+        excluded_items: &[ItemId],
+        query_leafs: &[Leaf<D>],
+        aggregation: Aggregation,
+        opt: &QueryBuilder<D>,
+    ) -> Result<Vec<(ItemId, f32)>> {
+        if self.items.is_empty() || query_leafs.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let nodes = TxnNodes { reader: self, rtxn };
+        let trace = &mut QueryTrace::default();
+        let scratch = &mut QueryScratch::default();
+        self.nns_candidates(&nodes, excluded_items, query_leafs, opt, trace, scratch)?;
+        let distance = |leaf: &Leaf<D>| {
+            aggregation.aggregate(query_leafs.iter().map(|query| D::built_distance(query, leaf)))
+        };
+        let mut results = Vec::new();
+        self.nns_top_k(&nodes, opt, trace, scratch, distance, &mut results, None)?;
+        Ok(results)
+    }
+
+    /// Executes one query by leaf in parallel and returns the results in the same order.
+    /// The leafs are associated with the item they come from, if any.
+    ///
+    /// Since the transaction cannot be shared between threads, the queries explore the trees in
+    /// parallel until they reach a tree node that was not fetched yet. The missing tree nodes are
+    /// then fetched all at once and the explorations resume until they are all done. When the
+    /// queries explore a large part of the index, all the tree nodes are gathered upfront instead.
+    /// Finally the leafs of all the candidates are fetched at once and the distances are computed
+    /// in parallel.
+    fn nns_by_leafs(
+        &self,
+        rtxn: &'t RoTxn,
+        query_leafs: &[(Option<ItemId>, Leaf<D>)],
+        opt: &QueryBuilder<D>,
+    ) -> Result<Vec<Vec<(ItemId, f32)>>> {
+        if self.items.is_empty() || query_leafs.is_empty() {
+            return Ok(vec![Vec::new(); query_leafs.len()]);
+        }
+
+        // The closures may borrow the transaction, they are only called from this thread.
+        let Some(shared) = SharedQuery::new(opt) else {
+            return query_leafs
+                .iter()
+                .map(|(query_item, query_leaf)| {
+                    let excluded_item = query_item.and_then(|item| opt.excluded_query_item(item));
+                    let trace = &mut QueryTrace::default();
+                    self.nns_by_leaf(rtxn, excluded_item.as_slice(), query_leaf, opt, trace, None)
+                })
+                .collect();
+        };
+
+        let mut nodes = ImmutableNodes::new(self);
+        let explored = (query_leafs.len() as u64).saturating_mul(opt.effective_search_k(1) as u64);
+        // The exhaustive search directly scores the items without exploring the trees
+        // and the radius usually limits the exploration to a small part of the trees.
+        if opt.strategy == SearchStrategy::Trees
+            && opt.pruning_radius(1).is_none()
+            && explored >= self.items.len()
+        {
+            nodes.fetch_trees(rtxn, self)?;
+        }
+
+        let mut explorations = query_leafs
+            .par_iter()
+            .map(|(query_item, query_leaf)| {
+                let opt = &shared.get();
+                let mut trace = QueryTrace::default();
+                let mut scratch = QueryScratch::default();
+                let excluded_item = query_item.and_then(|item| opt.excluded_query_item(item));
+                let excluded = excluded_item.as_slice();
+                let query_leafs = std::slice::from_ref(query_leaf);
+                self.init_candidates(excluded, query_leafs.len(), opt, &mut trace, &mut scratch);
+                let missing = self.explore_candidates(
+                    &nodes,
+                    excluded,
+                    query_leafs,
+                    opt,
+                    &mut trace,
+                    &mut scratch,
+                )?;
+                Ok((excluded_item, scratch, trace, missing))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        while explorations.iter().any(|(.., missing)| missing.is_some()) {
+            let missing = explorations.iter().filter_map(|(.., missing)| *missing);
+            nodes.fetch_tree_nodes(rtxn, self, missing)?;
+            explorations.par_iter_mut().zip(query_leafs).try_for_each(
+                |((excluded_item, scratch, trace, missing), (_, query_leaf))| -> Result<()> {
+                    if missing.is_some() {
+                        let opt = &shared.get();
+                        let excluded = excluded_item.as_slice();
+                        let query_leafs = std::slice::from_ref(query_leaf);
+                        *missing = self.explore_candidates(
+                            &nodes,
+                            excluded,
+                            query_leafs,
+                            opt,
+                            trace,
+                            scratch,
+                        )?;
+                    }
+                    Ok(())
+                },
+            )?;
+        }
+
+        let all_candidates =
+            explorations.iter().flat_map(|(_, scratch, ..)| &scratch.candidates).copied();
+        nodes.fetch_leafs(rtxn, self, all_candidates)?;
+
+        query_leafs
+            .par_iter()
+            .zip(explorations)
+            .map(|((_, query_leaf), (_, mut scratch, mut trace, _))| {
+                let opt = &shared.get();
+                let (trace, scratch) = (&mut trace, &mut scratch);
+                let distance = |leaf: &Leaf<D>| D::built_distance(query_leaf, leaf);
+                let mut results = Vec::new();
+                self.nns_top_k(&nodes, opt, trace, scratch, distance, &mut results, None)?;
+                Ok(results)
+            })
+            .collect()
     }
 
-    /// Get a generic read node from the database using the version of the database found while creating the reader.
-    /// Must be used every time we retrieve a node in this file.
-    fn database_get(&self, rtxn: &'t RoTxn, key: &Key) -> Result<Option<GenericReadNode<D>>> {
-        match self.version {
-            // the node format didn't change between v0.4.0 and v0.6.0 included
-            Version { major: 0, minor: 4..=6, patch: _ } => Ok(self
-                .database
-                .remap_data_type::<GenericReadNodeCodecFromV0_4_0<D>>()
-                .get(rtxn, key)?),
-            Version { major: 0, minor: 7, patch: _ } => Ok(self
-                .database
-                .remap_data_type::<GenericReadNodeCodecFromV0_7_0<D>>()
-                .get(rtxn, key)?),
-            version => Err(Error::UnknownVersion { version }),
-        }
+    /// Explores the trees, starting with the most promising branches, and stores the sorted and
+    /// deduplicated list of items that must be scored against the queries in the scratch.
+    /// Every query has its own priority for a branch and the best one is used to rank it.
+    fn nns_candidates(
+        &self,
+        nodes: &TxnNodes<'_, 't, D>,
+        excluded_items: &[ItemId],
+        query_leafs: &[Leaf<D>],
+        opt: &QueryBuilder<D>,
+        trace: &mut QueryTrace,
+        scratch: &mut QueryScratch,
+    ) -> Result<()> {
+        self.init_candidates(excluded_items, query_leafs.len(), opt, trace, scratch);
+        let missing =
+            self.explore_candidates(nodes, excluded_items, query_leafs, opt, trace, scratch)?;
+        debug_assert!(missing.is_none(), "the transaction can read all the tree nodes");
+        Ok(())
     }
 
-    fn nns_by_leaf(
+    /// Prepares the scratch to explore the trees from their roots, see [`Self::explore_candidates`].
+    /// The exhaustive search directly stores all the items to score.
+    fn init_candidates(
         &self,
-        rtxn: &'t RoTxn,
-        query_leaf: &Leaf<D>,
+        excluded_items: &[ItemId],
+        n_queries: usize,
         opt: &QueryBuilder<D>,
-    ) -> Result<Vec<(ItemId, f32)>> {
-        if self.items.is_empty() {
-            return Ok(Vec::new());
+        trace: &mut QueryTrace,
+        scratch: &mut QueryScratch,
+    ) {
+        let QueryScratch {
+            exploration,
+            candidates: nns,
+            distinct,
+            groups,
+            predicate_memo,
+            promising,
+            ..
+        } = scratch;
+        exploration.clear();
+        nns.clear();
+        promising.clear();
+        distinct.clear();
+        groups.clear();
+        predicate_memo.clear();
+
+        trace.strategy = opt.strategy;
+        if trace.strategy == SearchStrategy::Exhaustive {
+            let items = opt.filter_items(excluded_items, predicate_memo, &self.items);
+            trace.candidates_before_filter = self.items.len();
+            trace.candidates_after_filter = items.len();
+            nns.extend(items.iter());
+            // There is no better order to score the items in, the first ones are always scored.
+            promising.extend(nns.iter().take(opt.promising_count()));
+            return;
         }
+
         // Since the datastructure describes a kind of btree, the capacity is something in the order of:
         // The number of root nodes + log2 of the total number of vectors.
-        let mut queue =
-            BinaryHeap::with_capacity(self.roots.len() + self.items.len().ilog2() as usize);
-        let search_k = opt.search_k.map_or(opt.count * self.roots.len(), NonZeroUsize::get);
-        let search_k = opt
-            .oversampling
-            .map_or(search_k.saturating_mul(D::DEFAULT_OVERSAMPLING), |oversampling| {
-                search_k.saturating_mul(oversampling.get())
-            });
+        exploration.queue.reserve(self.roots.len() + self.items.len().ilog2() as usize);
+        trace.search_k = opt.effective_search_k(n_queries);
+        exploration.push_roots(opt.roots(), n_queries);
+    }
+
+    /// Explores the trees prepared by [`Self::init_candidates`] and once enough items are
+    /// collected, sorts and deduplicates them. The exploration stops early and returns the
+    /// next tree node to explore when it is not available in the nodes, it can then be
+    /// resumed by calling this method again once the node is available.
+    fn explore_candidates(
+        &self,
+        nodes: &impl NodeSource<'t, D>,
+        excluded_items: &[ItemId],
+        query_leafs: &[Leaf<D>],
+        opt: &QueryBuilder<D>,
+        trace: &mut QueryTrace,
+        scratch: &mut QueryScratch,
+    ) -> Result<Option<NodeId>> {
+        if trace.strategy == SearchStrategy::Exhaustive {
+            return Ok(None);
+        }
 
-        // Insert all the root nodes and associate them to the highest distance.
-        queue.extend(repeat(OrderedFloat(f32::INFINITY)).zip(self.roots.iter().map(NodeId::tree)));
+        let QueryScratch {
+            exploration,
+            candidates: nns,
+            distinct,
+            groups,
+            predicate_memo,
+            promising,
+            ..
+        } = scratch;
+        let search_k = trace.search_k;
+
+        // The distinct items are only tracked when we must ensure the count.
+        let max_search_k = opt.max_search_k.map_or(search_k, NonZeroUsize::get);
+        // The distinct groups are only tracked when we collapse the results,
+        // we explore until enough groups are found unless a limit is specified.
+        let max_collapse_k = opt.max_search_k.map_or(usize::MAX, NonZeroUsize::get);
+
+        while nns.len() < search_k
+            || (nns.len() < max_search_k
+                && opt.max_search_k.is_some()
+                && distinct.len() < opt.count as u64)
+            || (nns.len() < max_collapse_k && opt.collapse.is_some() && groups.len() < opt.count)
+        {
+            if opt.interrupted() {
+                trace.partial = true;
+                break;
+            }
 
-        let mut nns = Vec::new();
-        while nns.len() < search_k {
-            let (OrderedFloat(dist), item) = match queue.pop() {
-                Some(out) => out,
-                None => break,
+            let found = |item| {
+                nns.push(item);
+                if opt.max_search_k.is_some() {
+                    distinct.insert(item);
+                }
+                if let Some((group, _)) = opt.collapse {
+                    groups.insert(group(item));
+                }
             };
+            match self.explore_step(
+                nodes,
+                excluded_items,
+                query_leafs,
+                opt,
+                trace,
+                exploration,
+                predicate_memo,
+                found,
+            )? {
+                ExploreStep::Explored => (),
+                ExploreStep::Missing(node_id) => return Ok(Some(node_id)),
+                ExploreStep::Exhausted => break,
+            }
+        }
 
-            let key = Key::new(self.index, item);
-            match self.database_get(rtxn, &key)?.ok_or(Error::missing_key(key))? {
-                GenericReadNode::Leaf(_) => {
-                    if opt.candidates.map_or(true, |c| c.contains(item.item)) {
-                        nns.push(item.unwrap_item());
+        // The candidates are collected from the most promising branches first,
+        // the first ones are always scored even if the query is interrupted.
+        let promising_count = opt.promising_count();
+        if promising_count != 0 {
+            let mut seen = RoaringBitmap::new();
+            let first = nns.iter().copied().filter(|&item| seen.insert(item));
+            promising.extend(first.take(promising_count));
+        }
+
+        // The items updated since the last build may not be in the trees, they are always scored.
+        let updated = &self.pending.updated;
+        if !updated.is_empty() {
+            trace.candidates_before_filter += updated.len();
+            let updated = opt.filter_items(excluded_items, predicate_memo, updated);
+            trace.candidates_after_filter += updated.len();
+            nns.extend(updated.iter());
+        }
+
+        // To avoid calculating distance multiple times for any items, sort by id and dedup by id.
+        nns.sort_unstable();
+        nns.dedup();
+
+        Ok(None)
+    }
+
+    /// Pops the most promising tree node of the `exploration` and explores it. The children of
+    /// a split plane are queued with their priority for every query, unless they are out of the
+    /// radius, and the items of the descendants accepted by the query are given to `found`.
+    /// The tree node is left in the queue when it is not available in the nodes.
+    #[allow(clippy::too_many_arguments)]
+    fn explore_step(
+        &self,
+        nodes: &impl NodeSource<'t, D>,
+        excluded_items: &[ItemId],
+        query_leafs: &[Leaf<D>],
+        opt: &QueryBuilder<D>,
+        trace: &mut QueryTrace,
+        exploration: &mut Exploration,
+        predicate_memo: &mut PredicateMemo,
+        mut found: impl FnMut(ItemId),
+    ) -> Result<ExploreStep> {
+        let Exploration { queue, priorities, margins } = exploration;
+        let item = match queue.peek() {
+            Some(&(_, item, _)) => item,
+            None => return Ok(ExploreStep::Exhausted),
+        };
+        if item.mode == NodeMode::Tree && !nodes.contains_tree_node(item) {
+            return Ok(ExploreStep::Missing(item));
+        }
+        let (_, item, offset) = queue.pop().unwrap();
+        trace.nodes_popped += 1;
+
+        // Before v0.7.0 the split planes could directly point to the leafs.
+        if item.mode == NodeMode::Item {
+            trace.candidates_before_filter += 1;
+            let excluded = opt.excluded.is_some_and(|excluded| excluded.contains(item.item));
+            if !excluded && opt.accepts(excluded_items, predicate_memo, item.item) {
+                trace.candidates_after_filter += 1;
+                found(item.item);
+            }
+            return Ok(ExploreStep::Explored);
+        }
+
+        match &*nodes.tree_node(item)? {
+            GenericReadNode::Leaf(_) => unreachable!("leafs are only stored under item keys"),
+            GenericReadNode::Descendants(GenericReadDescendants { descendants }) => {
+                trace.descendants += 1;
+                trace.candidates_before_filter += descendants.len();
+                let accept = |item| {
+                    if opt.accepts(excluded_items, predicate_memo, item) {
+                        trace.candidates_after_filter += 1;
+                        found(item);
+                    }
+                };
+                match opt.excluded {
+                    // The excluded items are removed from the whole descendants at once.
+                    Some(excluded) => {
+                        let bytes = descendants.as_bytes();
+                        let mut items = RoaringBitmap::deserialize_unchecked_from(bytes)?;
+                        items -= excluded;
+                        items.iter().for_each(accept);
                     }
+                    // Otherwise the descendants are filtered while being read from the
+                    // database to avoid building a bitmap for every node.
+                    None => descendants.iter().for_each(accept),
                 }
-                GenericReadNode::Descendants(Descendants { descendants }) => {
-                    if let Some(candidates) = opt.candidates {
-                        nns.extend((descendants.into_owned() & candidates).iter());
+            }
+            GenericReadNode::SplitPlaneNormal(GenericReadSplitPlaneNormal {
+                normal,
+                left,
+                right,
+            }) => {
+                trace.split_planes += 1;
+                margins.clear();
+                margins.extend(query_leafs.iter().map(|query_leaf| match normal {
+                    Some(normal) => D::margin(normal, query_leaf),
+                    None => 0.0,
+                }));
+
+                let radius = opt.pruning_radius(query_leafs.len());
+                for (side, child) in [(Side::Left, *left), (Side::Right, *right)] {
+                    let child_offset = priorities.len();
+                    let mut best = f32::NEG_INFINITY;
+                    for (i, &margin) in margins.iter().enumerate() {
+                        let priority = D::pq_distance(priorities[offset + i], margin, side);
+                        priorities.push(priority);
+                        best = best.max(priority);
+                    }
+                    if radius.is_some_and(|radius| out_of_radius::<D>(radius, best)) {
+                        priorities.truncate(child_offset);
                     } else {
-                        nns.extend(descendants.iter());
+                        queue.push((OrderedFloat(best), child, child_offset));
                     }
                 }
-                GenericReadNode::SplitPlaneNormal(GenericReadSplitPlaneNormal {
-                    normal,
-                    left,
-                    right,
-                }) => {
-                    let margin = match normal {
-                        Some(normal) => D::margin(&normal, query_leaf),
-                        None => 0.0,
-                    };
-                    queue.push((OrderedFloat(D::pq_distance(dist, margin, Side::Left)), left));
-                    queue.push((OrderedFloat(D::pq_distance(dist, margin, Side::Right)), right));
-                }
             }
         }
 
-        // Get distances for all items
-        // To avoid calculating distance multiple times for any items, sort by id and dedup by id.
-        nns.sort_unstable();
-        nns.dedup();
+        Ok(ExploreStep::Explored)
+    }
 
-        let mut nns_distances = Vec::with_capacity(nns.len());
-        for nn in nns {
-            let key = Key::item(self.index, nn);
-            let GenericReadNode::Leaf(leaf) =
-                self.database_get(rtxn, &key)?.ok_or(Error::missing_key(key))?
-            else {
-                unreachable!()
-            };
-            let distance = D::built_distance(query_leaf, &leaf);
-            nns_distances.push((OrderedFloat(distance), nn));
+    /// Computes the distances of the candidates of the scratch with the provided function and
+    /// keeps the `count` nearest ones in the distances of the scratch, the nearest first.
+    /// The items are identified by their position in the candidates, as the leafs if they are kept.
+    fn nns_nearests(
+        &self,
+        nodes: &impl NodeSource<'t, D>,
+        opt: &QueryBuilder<D>,
+        trace: &mut QueryTrace,
+        scratch: &mut QueryScratch,
+        distance: impl Fn(&Leaf<D>) -> f32,
+        keep_leafs: bool,
+    ) -> Result<Vec<Option<Leaf<'t, D>>>> {
+        let QueryScratch { candidates, promising, distances: nns_distances, .. } = scratch;
+        nns_distances.clear();
+        let mut leafs = Vec::new();
+        if keep_leafs {
+            leafs.resize_with(candidates.len(), || None);
+        }
+
+        // The most promising candidates are scored first, even if the query is interrupted,
+        // so that the results don't only contain the candidates with the lowest ids.
+        for &item in promising.iter() {
+            let position = candidates.binary_search(&item).expect("promising items are candidates");
+            let leaf = nodes.leaf(item)?;
+            nns_distances.push((OrderedFloat(distance(&leaf)), position as u32));
+            if keep_leafs {
+                leafs[position] = Some(leaf);
+            }
+        }
+        promising.sort_unstable();
+
+        // The candidates are sorted by id, ranking them by position breaks the ties the same way.
+        // When the exploration was interrupted, all the candidates it already found are scored.
+        let interruptible = !trace.partial;
+        if promising.len() < candidates.len() {
+            nodes.for_each_leaf(candidates, |position, leaf| {
+                if promising.binary_search(&candidates[position]).is_ok() {
+                    return true;
+                }
+                if interruptible && opt.interrupted() {
+                    trace.partial = true;
+                    return false;
+                }
+
+                nns_distances.push((OrderedFloat(distance(&leaf)), position as u32));
+                if keep_leafs {
+                    leafs[position] = Some(leaf);
+                }
+                true
+            })?;
+        }
+        trace.distance_computations += nns_distances.len();
+
+        // Get k nearest neighbors, or the pool of items to diversify
+        let k = match opt.diversity {
+            Some((_, pool_size)) => pool_size.get().max(opt.count),
+            None => opt.count,
+        };
+        match opt.collapse {
+            Some((group, per_group)) => {
+                top_k_by_group(nns_distances, candidates, group, per_group.get(), opt.count)
+            }
+            None => median_based_top_k(nns_distances, k.min(candidates.len())),
+        }
+
+        // The results are sorted, there is no closer item after the first one out of range.
+        if let Some(max) = opt.max_distance {
+            let within = nns_distances.partition_point(|&(OrderedFloat(dist), _)| {
+                D::is_within(D::normalized_distance(dist, self.dimensions), max)
+            });
+            nns_distances.truncate(within);
         }
 
-        // Get k nearest neighbors
-        let k = opt.count.min(nns_distances.len());
-        let top_k = median_based_top_k(nns_distances, k);
-        let mut output = Vec::with_capacity(top_k.len());
-        for (OrderedFloat(dist), item) in top_k {
-            output.push((item, D::normalized_distance(dist, self.dimensions)));
+        Ok(leafs)
+    }
+
+    /// Computes the distances of the candidates of the scratch with the provided function
+    /// and writes the `count` nearest ones into `output`, along with their vectors if asked.
+    #[allow(clippy::too_many_arguments)]
+    fn nns_top_k(
+        &self,
+        nodes: &impl NodeSource<'t, D>,
+        opt: &QueryBuilder<D>,
+        trace: &mut QueryTrace,
+        scratch: &mut QueryScratch,
+        distance: impl Fn(&Leaf<D>) -> f32,
+        output: &mut Vec<(ItemId, f32)>,
+        mut vectors: Option<&mut Vec<Vec<f32>>>,
+    ) -> Result<()> {
+        // The diversification compares the nearest items between them. The leafs are borrowed from
+        // the database when possible, keeping them is cheaper than reading the nearest ones again.
+        let diversify = opt.diversity.is_some() && opt.collapse.is_none();
+        let keep_leafs = vectors.is_some() || diversify;
+        let mut leafs = self.nns_nearests(nodes, opt, trace, scratch, distance, keep_leafs)?;
+
+        let QueryScratch { candidates, distances, .. } = scratch;
+        let score = |dist| opt.score(D::normalized_distance(dist, self.dimensions));
+        let to_vector = |leaf: Leaf<D>| {
+            let mut vector = leaf.vector.to_vec();
+            vector.truncate(self.dimensions);
+            vector
+        };
+
+        match (opt.diversity, opt.collapse) {
+            (Some((lambda, _)), None) => {
+                // The diversification needs the leafs of the nearest items.
+                let mut pool = Vec::with_capacity(distances.len());
+                for &(OrderedFloat(dist), position) in distances.iter() {
+                    let item = candidates[position as usize];
+                    let leaf =
+                        leafs[position as usize].take().expect("candidates are deduplicated");
+                    pool.push((dist, item, leaf));
+                }
+                for (dist, item, leaf) in maximal_marginal_relevance(pool, lambda, opt.count) {
+                    output.push((item, score(dist)));
+                    if let Some(vectors) = vectors.as_mut() {
+                        vectors.push(to_vector(leaf));
+                    }
+                }
+            }
+            _ => {
+                for &(OrderedFloat(dist), position) in distances.iter() {
+                    output.push((candidates[position as usize], score(dist)));
+                    if let Some(vectors) = vectors.as_mut() {
+                        let leaf = leafs[position as usize].take();
+                        vectors.push(to_vector(leaf.expect("candidates are deduplicated")));
+                    }
+                }
+            }
         }
-        Ok(output)
+
+        Ok(())
     }
 
     #[cfg(feature = "plot")]
@@ -429,7 +2259,7 @@ impl<'t, D: Distance> Reader<'t, D> {
             while let Some(key) = explore.pop() {
                 match self.database_get(rtxn, &key)?.unwrap() {
                     GenericReadNode::Leaf(_) => (),
-                    GenericReadNode::Descendants(Descendants { descendants: _ }) => {
+                    GenericReadNode::Descendants(GenericReadDescendants { descendants: _ }) => {
                         writeln!(writer, "\t\t{} [label=\"{}\"]", key.node.item, key.node.item,)?
                     }
                     GenericReadNode::SplitPlaneNormal(GenericReadSplitPlaneNormal {
@@ -482,7 +2312,9 @@ impl<'t, D: Distance> Reader<'t, D> {
 
         match self.database_get(rtxn, &Key::new(self.index, node_id))?.unwrap() {
             GenericReadNode::Leaf(_) => Ok(1),
-            GenericReadNode::Descendants(Descendants { descendants }) => Ok(descendants.len()),
+            GenericReadNode::Descendants(GenericReadDescendants { descendants }) => {
+                Ok(descendants.len())
+            }
             GenericReadNode::SplitPlaneNormal(GenericReadSplitPlaneNormal {
                 normal: _,
                 left,
@@ -552,7 +2384,7 @@ impl<'t, D: Distance> Reader<'t, D> {
         rtxn: &RoTxn,
         node_id: NodeId,
     ) -> Result<(RoaringBitmap, RoaringBitmap)> {
-        use crate::node::SplitPlaneNormal;
+        use crate::node::{Descendants, SplitPlaneNormal};
 
         match self
             .database
@@ -589,6 +2421,468 @@ impl<'t, D: Distance> Reader<'t, D> {
     }
 }
 
+/// An iterator over the nearest neighbors of a query, see [`QueryBuilder::iter_by_item`].
+pub struct NnsIter<'a, D: Distance> {
+    opt: &'a QueryBuilder<'a, D>,
+    nodes: TxnNodes<'a, 'a, D>,
+    state: NnsContinuation<D>,
+}
+
+/// The state of an [`NnsIter`] that doesn't borrow the read transaction.
+/// It can be resumed with [`QueryBuilder::resume`].
+pub struct NnsContinuation<D: Distance> {
+    excluded_item: Option<ItemId>,
+    query_leaf: Leaf<'static, D>,
+    /// The tree nodes to explore.
+    exploration: Exploration,
+    /// The items that were already scored.
+    seen: RoaringBitmap,
+    /// The items already accepted or rejected by the predicate.
+    predicate_memo: PredicateMemo,
+    /// The scored items that were not returned yet, the closest first.
+    pending: BinaryHeap<Reverse<(OrderedFloat<f32>, ItemId)>>,
+}
+
+impl<'a, D: Distance> NnsIter<'a, D> {
+    fn new(
+        opt: &'a QueryBuilder<'a, D>,
+        rtxn: &'a RoTxn,
+        query_item: Option<ItemId>,
+        query_leaf: Leaf<'static, D>,
+    ) -> Result<Self> {
+        let mut iter = NnsIter {
+            opt,
+            nodes: TxnNodes { reader: opt.reader, rtxn },
+            state: NnsContinuation {
+                excluded_item: query_item.and_then(|item| opt.excluded_query_item(item)),
+                query_leaf,
+                exploration: Exploration::default(),
+                seen: RoaringBitmap::new(),
+                predicate_memo: PredicateMemo::default(),
+                pending: BinaryHeap::new(),
+            },
+        };
+
+        let NnsContinuation { excluded_item, exploration, predicate_memo, .. } = &mut iter.state;
+        let excluded_items = excluded_item.as_slice();
+        let items = if opt.strategy == SearchStrategy::Exhaustive {
+            opt.filter_items(excluded_items, predicate_memo, &opt.reader.items).into_owned()
+        } else {
+            exploration.push_roots(opt.roots(), 1);
+            // The items updated since the last build may not be in the trees.
+            let updated = &opt.reader.pending.updated;
+            opt.filter_items(excluded_items, predicate_memo, updated).into_owned()
+        };
+        iter.score(items)?;
+
+        Ok(iter)
+    }
+
+    /// Returns the state of the iterator to resume it later on,
+    /// without holding the read transaction.
+    pub fn into_continuation(self) -> NnsContinuation<D> {
+        self.state
+    }
+
+    /// Explores the trees until `search_k` items are collected, or the query is interrupted,
+    /// and scores the new ones.
+    fn explore(&mut self) -> Result<()> {
+        let NnsContinuation {
+            excluded_item, query_leaf, exploration, seen, predicate_memo, ..
+        } = &mut self.state;
+        let search_k = self.opt.effective_search_k(1);
+        let excluded_items = excluded_item.as_slice();
+        let query_leafs = std::slice::from_ref(&*query_leaf);
+        let trace = &mut QueryTrace::default();
+
+        let mut collected = 0;
+        let mut new_items = RoaringBitmap::new();
+        while collected < search_k && !self.opt.interrupted() {
+            let found = |item| {
+                collected += 1;
+                new_items.insert(item);
+            };
+            match self.opt.reader.explore_step(
+                &self.nodes,
+                excluded_items,
+                query_leafs,
+                self.opt,
+                trace,
+                exploration,
+                predicate_memo,
+                found,
+            )? {
+                ExploreStep::Explored => (),
+                ExploreStep::Missing(_) => {
+                    unreachable!("the transaction can read all the tree nodes")
+                }
+                ExploreStep::Exhausted => break,
+            }
+        }
+
+        new_items -= &*seen;
+        self.score(new_items)
+    }
+
+    /// Computes the distances between the query and the items and stores them until they are returned.
+    fn score(&mut self, items: RoaringBitmap) -> Result<()> {
+        let NnsContinuation { query_leaf, seen, pending, .. } = &mut self.state;
+        for item in &items {
+            let leaf = self.nodes.leaf(item)?;
+            let distance = D::built_distance(query_leaf, &leaf);
+            pending.push(Reverse((OrderedFloat(distance), item)));
+        }
+        *seen |= items;
+        Ok(())
+    }
+}
+
+impl<D: Distance> Iterator for NnsIter<'_, D> {
+    type Item = Result<(ItemId, f32)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // We always keep `count` items scored to return them in the best possible order.
+            while self.state.pending.len() < self.opt.count
+                && !self.state.exploration.queue.is_empty()
+                && !self.opt.interrupted()
+            {
+                if let Err(e) = self.explore() {
+                    return Some(Err(e));
+                }
+            }
+
+            let Reverse((OrderedFloat(dist), item)) = self.state.pending.pop()?;
+            let distance = D::normalized_distance(dist, self.opt.reader.dimensions);
+            if self.opt.max_distance.is_none_or(|max| D::is_within(distance, max)) {
+                return Some(Ok((item, self.opt.score(distance))));
+            }
+        }
+    }
+}
+
+/// Gives access to the nodes explored while searching in the trees.
+trait NodeSource<'t, D: Distance> {
+    /// Returns the tree node identified by the given id.
+    fn tree_node(&self, node_id: NodeId) -> Result<Cow<'_, GenericReadNode<'t, D>>>;
+
+    /// Returns `false` if the tree node must be fetched before it can be explored.
+    fn contains_tree_node(&self, node_id: NodeId) -> bool;
+
+    /// Returns the leaf of the given item.
+    fn leaf(&self, item: ItemId) -> Result<Leaf<'t, D>>;
+
+    /// Calls `f` with the position and the leaf of every one of the sorted `items`,
+    /// in order, until it returns `false`.
+    fn for_each_leaf(
+        &self,
+        items: &[ItemId],
+        mut f: impl FnMut(usize, Leaf<'t, D>) -> bool,
+    ) -> Result<()> {
+        for (position, &item) in items.iter().enumerate() {
+            if !f(position, self.leaf(item)?) {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the nodes directly from the transaction.
+struct TxnNodes<'a, 't, D: Distance> {
+    reader: &'a Reader<'t, D>,
+    rtxn: &'t RoTxn<'t>,
+}
+
+impl<'t, D: Distance> NodeSource<'t, D> for TxnNodes<'_, 't, D> {
+    fn tree_node(&self, node_id: NodeId) -> Result<Cow<'_, GenericReadNode<'t, D>>> {
+        let key = Key::new(self.reader.index, node_id);
+        self.reader.database_get(self.rtxn, &key)?.map(Cow::Owned).ok_or(Error::missing_key(key))
+    }
+
+    fn contains_tree_node(&self, _node_id: NodeId) -> bool {
+        true
+    }
+
+    fn leaf(&self, item: ItemId) -> Result<Leaf<'t, D>> {
+        let key = Key::item(self.reader.index, item);
+        match self.reader.database_get(self.rtxn, &key)?.ok_or(Error::missing_key(key))? {
+            GenericReadNode::Leaf(leaf) => Ok(leaf),
+            GenericReadNode::Descendants(_) | GenericReadNode::SplitPlaneNormal(_) => {
+                unreachable!()
+            }
+        }
+    }
+
+    fn for_each_leaf(
+        &self,
+        items: &[ItemId],
+        mut f: impl FnMut(usize, Leaf<'t, D>) -> bool,
+    ) -> Result<()> {
+        let Reader { database, index, version, .. } = *self.reader;
+        if !is_dense(items.len() as u64, self.reader.items.len()) {
+            for (position, &item) in items.iter().enumerate() {
+                if !f(position, self.leaf(item)?) {
+                    break;
+                }
+            }
+            return Ok(());
+        }
+
+        // The leafs are walked with a single cursor and the ones that are not asked are skipped.
+        let mut items = items.iter().copied().enumerate().peekable();
+        for result in database
+            .remap_types::<PrefixCodec, Bytes>()
+            .prefix_iter(self.rtxn, &Prefix::item(index))?
+            .remap_key_type::<KeyCodec>()
+        {
+            let Some(&(position, item)) = items.peek() else { break };
+            let (key, bytes) = result?;
+            if key.node.item < item {
+                continue;
+            } else if key.node.item > item {
+                break;
+            }
+            items.next();
+            if !f(position, decode_leaf(version, bytes)?) {
+                return Ok(());
+            }
+        }
+
+        match items.next() {
+            Some((_, item)) => Err(Error::missing_key(Key::item(index, item))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A snapshot of the nodes of an index that can be shared between threads.
+///
+/// The tree nodes are decoded the first time a query explores them and
+/// the decoded version is reused by all the other queries going through them.
+struct ImmutableNodes<'t, D: Distance> {
+    index: u16,
+    version: Version,
+    trees: IntMap<ItemId, (&'t [u8], OnceLock<GenericReadNode<'t, D>>)>,
+    leafs: IntMap<ItemId, &'t [u8]>,
+}
+
+impl<'t, D: Distance> ImmutableNodes<'t, D> {
+    /// Creates an empty snapshot of the nodes of the reader.
+    fn new(reader: &Reader<'t, D>) -> Self {
+        ImmutableNodes {
+            index: reader.index,
+            version: reader.version,
+            trees: IntMap::default(),
+            leafs: IntMap::default(),
+        }
+    }
+
+    /// Gathers all the tree nodes of the reader.
+    fn fetch_trees(&mut self, rtxn: &'t RoTxn, reader: &Reader<'t, D>) -> Result<()> {
+        for result in reader
+            .database
+            .remap_types::<PrefixCodec, Bytes>()
+            .prefix_iter(rtxn, &Prefix::tree(reader.index))?
+            .remap_key_type::<KeyCodec>()
+        {
+            let (key, bytes) = result?;
+            self.trees.insert(key.node.unwrap_tree(), (bytes, OnceLock::new()));
+        }
+        Ok(())
+    }
+
+    /// Fetches the given tree nodes to make them available to all the threads.
+    fn fetch_tree_nodes(
+        &mut self,
+        rtxn: &'t RoTxn,
+        reader: &Reader<'t, D>,
+        node_ids: impl IntoIterator<Item = NodeId>,
+    ) -> Result<()> {
+        let database = reader.database.remap_data_type::<Bytes>();
+        for node_id in node_ids {
+            if let Entry::Vacant(entry) = self.trees.entry(node_id.unwrap_tree()) {
+                let key = Key::new(self.index, node_id);
+                let bytes = database.get(rtxn, &key)?.ok_or(Error::missing_key(key))?;
+                entry.insert((bytes, OnceLock::new()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetches the leafs of the given items to make them available to all the threads.
+    fn fetch_leafs(
+        &mut self,
+        rtxn: &'t RoTxn,
+        reader: &Reader<'t, D>,
+        items: impl IntoIterator<Item = ItemId>,
+    ) -> Result<()> {
+        let items = RoaringBitmap::from_iter(items);
+        let database = reader.database.remap_data_type::<Bytes>();
+        if !is_dense(items.len(), reader.items.len()) {
+            for item in &items {
+                if let Entry::Vacant(entry) = self.leafs.entry(item) {
+                    let key = Key::item(self.index, item);
+                    entry.insert(database.get(rtxn, &key)?.ok_or(Error::missing_key(key))?);
+                }
+            }
+            return Ok(());
+        }
+
+        // The leafs are walked with a single cursor and the ones that are not asked are skipped.
+        for result in database
+            .remap_key_type::<PrefixCodec>()
+            .prefix_iter(rtxn, &Prefix::item(self.index))?
+            .remap_key_type::<KeyCodec>()
+        {
+            let (key, bytes) = result?;
+            if items.contains(key.node.item) {
+                self.leafs.insert(key.node.item, bytes);
+            }
+        }
+
+        match items.iter().find(|item| !self.leafs.contains_key(item)) {
+            Some(item) => Err(Error::missing_key(Key::item(self.index, item))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'t, D: Distance> NodeSource<'t, D> for ImmutableNodes<'t, D> {
+    fn tree_node(&self, node_id: NodeId) -> Result<Cow<'_, GenericReadNode<'t, D>>> {
+        let key = Key::new(self.index, node_id);
+        let (bytes, node) =
+            self.trees.get(&node_id.unwrap_tree()).ok_or(Error::missing_key(key))?;
+        match node.get() {
+            Some(node) => Ok(Cow::Borrowed(node)),
+            None => {
+                // Another thread may decode the same node in the meantime, the first one wins.
+                let decoded = decode_node(self.version, bytes)?;
+                Ok(Cow::Borrowed(node.get_or_init(|| decoded)))
+            }
+        }
+    }
+
+    fn contains_tree_node(&self, node_id: NodeId) -> bool {
+        self.trees.contains_key(&node_id.unwrap_tree())
+    }
+
+    fn leaf(&self, item: ItemId) -> Result<Leaf<'t, D>> {
+        let key = Key::item(self.index, item);
+        let bytes = self.leafs.get(&item).ok_or(Error::missing_key(key))?;
+        decode_leaf(self.version, bytes)
+    }
+}
+
+/// Returns `true` if all the items of a branch explored with the given priority are out of the
+/// `radius`, see [`Distance::MARGIN_BOUNDS_DISTANCE`]. The bound is loosened a little to keep the
+/// items lying on the split planes despite the rounding errors.
+fn out_of_radius<D: Distance>(radius: f32, priority: f32) -> bool {
+    !D::is_within(-priority * 0.999, radius)
+}
+
+/// Picks `count` items one by one from the `pool` sorted by distance to the query, minimizing
+/// `lambda * distance_to_query - (1 - lambda) * distance_to_the_closest_picked_item`.
+fn maximal_marginal_relevance<D: Distance>(
+    mut pool: Vec<(f32, ItemId, Leaf<D>)>,
+    lambda: f32,
+    count: usize,
+) -> Vec<(f32, ItemId, Leaf<D>)> {
+    let mut picked = Vec::with_capacity(count.min(pool.len()));
+    // The distance between every item of the pool and the closest picked item.
+    let mut closest = vec![f32::INFINITY; pool.len()];
+
+    // The nearest item is always picked first.
+    while picked.len() < count && !pool.is_empty() {
+        let best = if picked.is_empty() {
+            0
+        } else {
+            let scores = pool.iter().zip(&closest).map(|((dist, _, _), closest)| {
+                OrderedFloat(lambda * dist - (1.0 - lambda) * closest)
+            });
+            scores.enumerate().min_by_key(|(_, score)| *score).map_or(0, |(i, _)| i)
+        };
+
+        let item = pool.remove(best);
+        closest.remove(best);
+        for ((_, _, leaf), closest) in pool.iter().zip(&mut closest) {
+            *closest = closest.min(D::built_distance(&item.2, leaf));
+        }
+        picked.push(item);
+    }
+
+    picked
+}
+
+/// Sorts the candidates by distance and keeps the `per_group` nearest ones of the `count` nearest
+/// groups. The candidates are identified by their position in the `candidates` list.
+fn top_k_by_group(
+    nns_distances: &mut Vec<(OrderedFloat<f32>, u32)>,
+    candidates: &[ItemId],
+    group: impl Fn(ItemId) -> u64,
+    per_group: usize,
+    count: usize,
+) {
+    nns_distances.sort_unstable();
+
+    let mut groups = IntMap::<u64, usize>::default();
+    nns_distances.retain(|&(_, position)| {
+        let len = groups.len();
+        match groups.entry(group(candidates[position as usize])) {
+            Entry::Occupied(mut entry) if *entry.get() < per_group => {
+                *entry.get_mut() += 1;
+                true
+            }
+            Entry::Vacant(entry) if len < count => {
+                entry.insert(1);
+                true
+            }
+            _ => false,
+        }
+    });
+}
+
+/// Associates the results with their vectors.
+fn with_vectors(
+    results: Vec<(ItemId, f32)>,
+    vectors: Vec<Vec<f32>>,
+) -> Vec<(ItemId, f32, Vec<f32>)> {
+    results
+        .into_iter()
+        .zip(vectors)
+        .map(|((item, distance), vector)| (item, distance, vector))
+        .collect()
+}
+
+/// Returns `true` if reading the leafs of `n_items` out of the `total` items is cheaper with a
+/// single cursor walk over all the leafs than by looking them up one by one.
+fn is_dense(n_items: u64, total: u64) -> bool {
+    n_items.saturating_mul(8) >= total
+}
+
+/// Decodes a leaf with the codec corresponding to the version of the database.
+fn decode_leaf<D: Distance>(version: Version, bytes: &[u8]) -> Result<Leaf<'_, D>> {
+    match decode_node(version, bytes)? {
+        GenericReadNode::Leaf(leaf) => Ok(leaf),
+        GenericReadNode::Descendants(_) | GenericReadNode::SplitPlaneNormal(_) => unreachable!(),
+    }
+}
+
+/// Decodes a node with the codec corresponding to the version of the database.
+fn decode_node<D: Distance>(version: Version, bytes: &[u8]) -> Result<GenericReadNode<'_, D>> {
+    let node = match version {
+        // the node format didn't change between v0.4.0 and v0.6.0 included
+        Version { major: 0, minor: 4..=6, patch: _ } => {
+            GenericReadNodeCodecFromV0_4_0::bytes_decode(bytes)
+        }
+        Version { major: 0, minor: 7, patch: _ } => {
+            GenericReadNodeCodecFromV0_7_0::bytes_decode(bytes)
+        }
+        version => return Err(Error::UnknownVersion { version }),
+    };
+    node.map_err(|e| heed::Error::Decoding(e).into())
+}
+
 pub fn item_leaf<'a, D: Distance>(
     database: Database<D>,
     index: u16,
@@ -604,37 +2898,32 @@ pub fn item_leaf<'a, D: Distance>(
 }
 
 // Based on https://quickwit.io/blog/top-k-complexity, implemented in https://github.com/meilisearch/arroy/pull/129
-pub fn median_based_top_k(
-    v: Vec<(OrderedFloat<f32>, u32)>,
-    k: usize,
-) -> Vec<(OrderedFloat<f32>, u32)> {
-    let mut threshold = (OrderedFloat(f32::MAX), u32::MAX);
-    let mut buffer = Vec::with_capacity(2 * k.max(1));
-
-    // prefill with no threshold checks
-    let mut v = v.into_iter();
-    buffer.extend((&mut v).take(2 * k));
+// The vector is reused as the buffer, the `k` nearest items are left sorted in the vector.
+pub fn median_based_top_k(v: &mut Vec<(OrderedFloat<f32>, u32)>, k: usize) {
+    if k == 0 {
+        v.clear();
+        return;
+    }
 
-    for item in v {
-        if item >= threshold {
+    let mut threshold = (OrderedFloat(f32::MAX), u32::MAX);
+    // The buffer is the beginning of the vector, it never catches up with the items to read.
+    let mut len = 0;
+    for i in 0..v.len() {
+        let item = v[i];
+        // prefill with no threshold checks
+        if i >= 2 * k && item >= threshold {
             continue;
         }
-        if buffer.len() == 2 * k {
-            let (_, &mut median, _) = buffer.select_nth_unstable(k - 1);
+        if len == 2 * k {
+            let (_, &mut median, _) = v[..len].select_nth_unstable(k - 1);
             threshold = median;
-            buffer.truncate(k);
-        }
-
-        // avoids buffer resizing from being inlined from vec.push()
-        let uninit = buffer.spare_capacity_mut();
-        uninit[0].write(item);
-        // SAFETY: we would have panicked above already
-        unsafe {
-            buffer.set_len(buffer.len() + 1);
+            len = k;
         }
+        v[len] = item;
+        len += 1;
     }
 
-    buffer.sort_unstable();
-    buffer.truncate(k);
-    buffer
+    v.truncate(len);
+    v.sort_unstable();
+    v.truncate(k);
 }
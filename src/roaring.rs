@@ -1,4 +1,5 @@
 use std::borrow::Cow;
+use std::{fmt, io};
 
 use heed::BoxedError;
 use roaring::RoaringBitmap;
@@ -16,9 +17,280 @@ impl heed::BytesDecode<'_> for RoaringBitmapCodec {
 impl heed::BytesEncode<'_> for RoaringBitmapCodec {
     type EItem = RoaringBitmap;
 
-    fn bytes_encode(item: &Self::EItem) -> Result<Cow<[u8]>, BoxedError> {
+    fn bytes_encode(item: &Self::EItem) -> Result<Cow<'_, [u8]>, BoxedError> {
         let mut bytes = Vec::with_capacity(item.serialized_size());
         item.serialize_into(&mut bytes)?;
         Ok(Cow::Owned(bytes))
     }
 }
+
+const SERIAL_COOKIE_NO_RUNCONTAINER: u32 = 12346;
+const SERIAL_COOKIE: u16 = 12347;
+const NO_OFFSET_THRESHOLD: usize = 4;
+const ARRAY_LIMIT: u64 = 4096;
+const BITMAP_BYTES: usize = 8 * 1024;
+
+/// A roaring bitmap read directly from its serialized form, without allocating.
+///
+/// The bytes are checked when the bitmap is created, it can then be iterated
+/// as many times as needed without decoding it into a [`RoaringBitmap`].
+#[derive(Clone, Copy)]
+pub struct SerializedBitmap<'a> {
+    /// The whole serialized bitmap.
+    bytes: &'a [u8],
+    /// The key and the cardinality minus one of every container.
+    descriptions: &'a [u8],
+    /// One bit by container set when it is a run container, empty if there are none.
+    runs: &'a [u8],
+    /// The stores of the containers, one after the other.
+    stores: &'a [u8],
+}
+
+impl<'a> SerializedBitmap<'a> {
+    /// Checks the bytes of a bitmap serialized in the portable format,
+    /// with or without run containers.
+    pub fn new(bytes: &'a [u8]) -> io::Result<SerializedBitmap<'a>> {
+        let cookie = read_u32(bytes, 0)?;
+        let (size, has_offsets, header_len) = if cookie == SERIAL_COOKIE_NO_RUNCONTAINER {
+            (read_u32(bytes, 4)? as usize, true, 8)
+        } else if cookie as u16 == SERIAL_COOKIE {
+            let size = (cookie >> 16) as usize + 1;
+            (size, size >= NO_OFFSET_THRESHOLD, 4)
+        } else {
+            return Err(invalid_data("unknown cookie value"));
+        };
+        if size > u16::MAX as usize + 1 {
+            return Err(invalid_data("size is greater than supported"));
+        }
+
+        let runs_len = if cookie as u16 == SERIAL_COOKIE { size.div_ceil(8) } else { 0 };
+        let runs = slice(bytes, header_len, runs_len)?;
+        let descriptions = slice(bytes, header_len + runs_len, size * 4)?;
+        let offsets_len = if has_offsets { size * 4 } else { 0 };
+        let stores_start = header_len + runs_len + size * 4 + offsets_len;
+        let bitmap = SerializedBitmap { bytes, descriptions, runs, stores: &bytes[0..0] };
+
+        // We make sure that all the stores fit in the bytes to never check them again.
+        let mut stores_len = 0;
+        for index in 0..size {
+            let rest = bytes.get(stores_start + stores_len..).unwrap_or_default();
+            stores_len += bitmap.store_len(index, rest)?;
+        }
+        let stores = slice(bytes, stores_start, stores_len)?;
+
+        // Like `RoaringBitmap::deserialize_from`, we reject the containers that are not sorted
+        // so that iterating over the bitmap always yields sorted and unique integers.
+        let mut rest = stores;
+        for index in 0..size {
+            if index > 0 && bitmap.key(index - 1) >= bitmap.key(index) {
+                return Err(invalid_data("container keys are not sorted"));
+            }
+            let (store, tail) = rest.split_at(bitmap.store_len(index, rest)?);
+            bitmap.check_store(index, store)?;
+            rest = tail;
+        }
+
+        Ok(SerializedBitmap { bytes: &bytes[..stores_start + stores_len], stores, ..bitmap })
+    }
+
+    /// Returns the serialized bitmap.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Returns the number of integers in the bitmap.
+    pub fn len(&self) -> u64 {
+        (0..self.n_containers()).map(|index| self.cardinality(index)).sum()
+    }
+
+    /// Returns an iterator over the integers of the bitmap, in ascending order.
+    pub fn iter(&self) -> SerializedBitmapIter<'a> {
+        SerializedBitmapIter {
+            bitmap: *self,
+            next_container: 0,
+            stores: self.stores,
+            key: 0,
+            store: StoreIter::Array(&[]),
+        }
+    }
+
+    fn n_containers(&self) -> usize {
+        self.descriptions.len() / 4
+    }
+
+    fn key(&self, index: usize) -> u16 {
+        u16::from_le_bytes([self.descriptions[index * 4], self.descriptions[index * 4 + 1]])
+    }
+
+    fn cardinality(&self, index: usize) -> u64 {
+        let bytes = [self.descriptions[index * 4 + 2], self.descriptions[index * 4 + 3]];
+        u16::from_le_bytes(bytes) as u64 + 1
+    }
+
+    fn is_run(&self, index: usize) -> bool {
+        self.runs.get(index / 8).is_some_and(|byte| byte & (1 << (index % 8)) != 0)
+    }
+
+    /// Returns the length of the store of the container, that must start at the beginning of `rest`.
+    fn store_len(&self, index: usize, rest: &[u8]) -> io::Result<usize> {
+        if self.is_run(index) {
+            Ok(2 + read_u16(rest, 0)? as usize * 4)
+        } else if self.cardinality(index) <= ARRAY_LIMIT {
+            Ok(self.cardinality(index) as usize * 2)
+        } else {
+            Ok(BITMAP_BYTES)
+        }
+    }
+
+    /// Checks that the integers of the store of the container are sorted, unique
+    /// and that there are as many of them as the cardinality of the container.
+    fn check_store(&self, index: usize, store: &[u8]) -> io::Result<()> {
+        let cardinality = if self.is_run(index) {
+            // The first run can start at zero, the next ones must start after the previous one.
+            let mut min_start = 0;
+            let mut cardinality = 0;
+            for run in store[2..].chunks_exact(4) {
+                let start = u16::from_le_bytes([run[0], run[1]]) as u32;
+                let end = start + u16::from_le_bytes([run[2], run[3]]) as u32;
+                if start < min_start {
+                    return Err(invalid_data("runs are not sorted or overlap"));
+                }
+                if end > u16::MAX as u32 {
+                    return Err(invalid_data("run overflows its container"));
+                }
+                cardinality += (end - start + 1) as u64;
+                min_start = end + 1;
+            }
+            cardinality
+        } else if self.cardinality(index) <= ARRAY_LIMIT {
+            let values =
+                store.chunks_exact(2).map(|value| u16::from_le_bytes([value[0], value[1]]));
+            if values.clone().zip(values.skip(1)).any(|(previous, value)| previous >= value) {
+                return Err(invalid_data("array values are not sorted"));
+            }
+            self.cardinality(index)
+        } else {
+            store
+                .chunks_exact(8)
+                .map(|word| u64::from_le_bytes(word.try_into().unwrap()).count_ones() as u64)
+                .sum()
+        };
+
+        if cardinality == self.cardinality(index) {
+            Ok(())
+        } else {
+            Err(invalid_data("cardinality doesn't match the store"))
+        }
+    }
+}
+
+impl fmt::Debug for SerializedBitmap<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// An iterator over the integers of a [`SerializedBitmap`].
+pub struct SerializedBitmapIter<'a> {
+    bitmap: SerializedBitmap<'a>,
+    next_container: usize,
+    /// The stores of the containers that were not visited yet.
+    stores: &'a [u8],
+    /// The key of the current container.
+    key: u16,
+    store: StoreIter<'a>,
+}
+
+/// Iterates over the low bits of the integers of a container.
+enum StoreIter<'a> {
+    /// The remaining sorted integers.
+    Array(&'a [u8]),
+    /// The remaining words, the bits of the current one and the number of words read.
+    Bitmap { words: &'a [u8], word: u64, read: u32 },
+    /// The remaining runs and the values left in the current one.
+    Run { runs: &'a [u8], next: u32, end: u32 },
+}
+
+impl Iterator for StoreIter<'_> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        match self {
+            StoreIter::Array(values) => {
+                let (value, rest) = values.split_first_chunk()?;
+                *values = rest;
+                Some(u16::from_le_bytes(*value))
+            }
+            StoreIter::Bitmap { words, word, read } => {
+                while *word == 0 {
+                    let (next, rest) = words.split_first_chunk()?;
+                    *words = rest;
+                    *word = u64::from_le_bytes(*next);
+                    *read += 1;
+                }
+                let value = (*read - 1) * 64 + word.trailing_zeros();
+                *word &= *word - 1;
+                Some(value as u16)
+            }
+            StoreIter::Run { runs, next, end } => {
+                if next > end {
+                    let (run, rest) = runs.split_first_chunk::<4>()?;
+                    *runs = rest;
+                    *next = u16::from_le_bytes([run[0], run[1]]) as u32;
+                    *end = *next + u16::from_le_bytes([run[2], run[3]]) as u32;
+                }
+                let value = *next;
+                *next += 1;
+                Some(value as u16)
+            }
+        }
+    }
+}
+
+impl Iterator for SerializedBitmapIter<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        loop {
+            if let Some(low) = self.store.next() {
+                return Some(((self.key as u32) << 16) | low as u32);
+            }
+
+            let index = self.next_container;
+            if index >= self.bitmap.n_containers() {
+                return None;
+            }
+            // The stores were checked when the bitmap was created.
+            let len = self.bitmap.store_len(index, self.stores).unwrap();
+            let (store, rest) = self.stores.split_at(len);
+            self.stores = rest;
+            self.next_container += 1;
+            self.key = self.bitmap.key(index);
+            self.store = if self.bitmap.is_run(index) {
+                StoreIter::Run { runs: &store[2..], next: 1, end: 0 }
+            } else if self.bitmap.cardinality(index) <= ARRAY_LIMIT {
+                StoreIter::Array(store)
+            } else {
+                StoreIter::Bitmap { words: store, word: 0, read: 0 }
+            };
+        }
+    }
+}
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn slice(bytes: &[u8], start: usize, len: usize) -> io::Result<&[u8]> {
+    let end = start.checked_add(len);
+    end.and_then(|end| bytes.get(start..end))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "truncated bitmap"))
+}
+
+fn read_u16(bytes: &[u8], start: usize) -> io::Result<u16> {
+    slice(bytes, start, 2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(bytes: &[u8], start: usize) -> io::Result<u32> {
+    slice(bytes, start, 4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+}
@@ -1,3 +1,5 @@
+use crate::SearchStrategy;
+
 /// The different stats of an arroy database.
 #[derive(Debug, Clone)]
 pub struct Stats {
@@ -20,3 +22,32 @@ pub struct TreeStats {
     /// Number of descendants nodes in the tree.
     pub descendants: usize,
 }
+
+/// A report of the work done by a single query, see [`QueryBuilder::explain_by_item`].
+///
+/// [`QueryBuilder::explain_by_item`]: crate::QueryBuilder::explain_by_item
+#[derive(Debug, Copy, Clone, Default)]
+pub struct QueryTrace {
+    /// The strategy used to retrieve the candidates.
+    pub strategy: SearchStrategy,
+    /// The number of items to collect from the trees, after the oversampling.
+    pub search_k: usize,
+    /// Number of nodes popped from the priority queue.
+    pub nodes_popped: usize,
+    /// Number of split plane normals evaluated against the query.
+    pub split_planes: usize,
+    /// Number of descendants nodes expanded into candidates.
+    pub descendants: usize,
+    /// Number of items encountered before applying the candidates filter.
+    /// The same item may be counted once per tree.
+    pub candidates_before_filter: u64,
+    /// Number of items that matched the candidates filter.
+    /// The same item may be counted once per tree.
+    pub candidates_after_filter: u64,
+    /// Number of distances computed between the query and the items,
+    /// one per distinct candidate.
+    pub distance_computations: usize,
+    /// Whether the query was interrupted by a deadline or a cancellation
+    /// and returned the best results among the items scored so far.
+    pub partial: bool,
+}
@@ -8,12 +8,14 @@ use rand::rngs::StdRng;
 use rand::SeedableRng;
 use tempfile::TempDir;
 
+use crate::distances::Euclidean;
 use crate::version::VersionCodec;
-use crate::{Database, Distance, MetadataCodec, NodeCodec, NodeMode, Reader};
+use crate::{Database, Distance, MetadataCodec, NodeCodec, NodeMode, Reader, Writer};
 
 mod binary_quantized;
 mod fit_in_memory;
 mod reader;
+mod serialized_bitmap;
 mod tmp_nodes;
 mod upgrade;
 mod writer;
@@ -102,6 +104,19 @@ fn create_database<D: Distance>() -> DatabaseHandle<D> {
     DatabaseHandle { env, database, tempdir: dir }
 }
 
+/// Creates an index of 100 items on a line, the item `i` is `[i, 0]`, built with 10 trees.
+fn create_line_database() -> DatabaseHandle<Euclidean> {
+    let handle = create_database::<Euclidean>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 2);
+    for i in 0..100 {
+        writer.add_item(&mut wtxn, i, &[i as f32, 0.0]).unwrap();
+    }
+    writer.builder(&mut rng()).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+    handle
+}
+
 fn rng() -> StdRng {
     StdRng::from_seed(std::array::from_fn(|_| 42))
 }
@@ -1,16 +1,18 @@
 use std::fmt::Display;
 use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use ordered_float::OrderedFloat;
 use proptest::collection::vec;
 use proptest::prelude::*;
+use rand::Rng;
 use roaring::RoaringBitmap;
 
 use super::*;
 use crate::distance::Cosine;
-use crate::distances::{Euclidean, Manhattan};
+use crate::distances::{DotProduct, Euclidean, Manhattan};
 use crate::reader::median_based_top_k;
-use crate::{ItemId, Reader, Writer};
+use crate::{Aggregation, ItemId, Key, MultiQuery, QueryScratch, Reader, SearchStrategy, Writer};
 
 pub struct NnsRes(pub Option<Vec<(ItemId, f32)>>);
 
@@ -28,6 +30,17 @@ impl Display for NnsRes {
     }
 }
 
+pub struct MultiRes(pub Vec<(u16, ItemId, f32)>);
+
+impl Display for MultiRes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, id, score) in &self.0 {
+            writeln!(f, "index({index}) id({id}): score({score})")?;
+        }
+        Ok(())
+    }
+}
+
 #[test]
 fn open_unfinished_db() {
     let handle = create_database();
@@ -280,6 +293,47 @@ fn try_reading_in_a_non_built_database() {
     "###);
 }
 
+#[test]
+fn stale_reads() {
+    let handle = create_line_database();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 2);
+    writer.del_item(&mut wtxn, 50).unwrap();
+    writer.add_item(&mut wtxn, 10, &[50.25, 0.0]).unwrap();
+    writer.add_item(&mut wtxn, 100, &[49.75, 0.0]).unwrap();
+    let error = Reader::open(&wtxn, 0, handle.database).unwrap_err();
+    insta::assert_snapshot!(error, @"The trees have not been built after an update on index 0");
+
+    // The pending changes are visible in the same transaction
+    let reader = Reader::<Euclidean>::open_stale(&wtxn, 0, handle.database).unwrap();
+    assert_eq!(reader.n_items(), 100);
+    assert!(!reader.item_ids().contains(50));
+    let results = reader.nns(4).by_vector(&wtxn, &[50.0, 0.0]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(results)), @r"
+    id(10): distance(0.25)
+    id(100): distance(0.25)
+    id(49): distance(1)
+    id(51): distance(1)
+    ");
+    let results = reader.nns(4).exhaustive().by_vector(&wtxn, &[50.0, 0.0]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(results)), @r"
+    id(10): distance(0.25)
+    id(100): distance(0.25)
+    id(49): distance(1)
+    id(51): distance(1)
+    ");
+    let query = reader.nns(4);
+    let results = query.iter_by_vector(&wtxn, &[50.0, 0.0]).unwrap().take(4);
+    insta::assert_snapshot!(NnsRes(Some(results.collect::<Result<_, _>>().unwrap())), @r"
+    id(10): distance(0.25)
+    id(100): distance(0.25)
+    id(49): distance(1)
+    id(51): distance(1)
+    ");
+    let results = reader.nns(2).by_items(&wtxn, &[10, 50]).unwrap();
+    insta::assert_snapshot!(format!("{results:?}"), @"[Some([(10, 0.0), (100, 0.5)]), None]");
+}
+
 proptest! {
     #[test]
     fn median_top_k_vs_binary_heap(
@@ -292,8 +346,1741 @@ proptest! {
             original.into_iter().enumerate().map(|(num, item)| (OrderedFloat(item), num as u32)).collect();
 
         let u = binary_heap_based_top_k(original.clone(), k);
-        let v = median_based_top_k(original, k);
+        let mut v = original;
+        median_based_top_k(&mut v, k);
 
         assert_eq!(u, v);
     }
 }
+
+#[test]
+fn batch_queries() {
+    let handle = create_line_database();
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+    let candidates = RoaringBitmap::from_iter((0..100).step_by(3));
+    let mut query = reader.nns(3);
+    query.candidates(&candidates);
+
+    let vectors = [[0.0, 0.0], [50.2, 0.0], [99.0, 0.0]];
+    let ret = query.by_vectors(&rtxn, &vectors).unwrap();
+    for (vector, ret) in vectors.iter().zip(&ret) {
+        assert_eq!(ret, &query.by_vector(&rtxn, vector).unwrap());
+    }
+    insta::assert_debug_snapshot!(ret, @r"
+    [
+        [
+            (
+                0,
+                0.0,
+            ),
+            (
+                3,
+                3.0,
+            ),
+            (
+                6,
+                6.0,
+            ),
+        ],
+        [
+            (
+                51,
+                0.79999924,
+            ),
+            (
+                48,
+                2.2000008,
+            ),
+            (
+                54,
+                3.7999992,
+            ),
+        ],
+        [
+            (
+                99,
+                0.0,
+            ),
+            (
+                96,
+                3.0,
+            ),
+            (
+                93,
+                6.0,
+            ),
+        ],
+    ]
+    ");
+
+    let items = [12, 1000, 42];
+    let ret = query.by_items(&rtxn, &items).unwrap();
+    for (item, ret) in items.iter().zip(ret) {
+        assert_eq!(ret, query.by_item(&rtxn, *item).unwrap());
+    }
+
+    // The large batches gather the tree nodes to explore them in parallel
+    let items: Vec<_> = (0..100).collect();
+    let ret = query.by_items(&rtxn, &items).unwrap();
+    for (item, ret) in items.iter().zip(ret) {
+        assert_eq!(ret, query.by_item(&rtxn, *item).unwrap());
+    }
+    query.exhaustive();
+    let ret = query.by_items(&rtxn, &items).unwrap();
+    for (item, ret) in items.iter().zip(ret) {
+        assert_eq!(ret, query.by_item(&rtxn, *item).unwrap());
+    }
+
+    let ret = query.by_vectors(&rtxn, &[[0.0, 0.0, 0.0]]).unwrap_err();
+    insta::assert_snapshot!(ret, @"Invalid vector dimensions. Got 3 but expected 2");
+}
+
+#[test]
+fn radius_search() {
+    let handle = create_database::<Euclidean>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    let mut query = reader.nns(usize::MAX);
+    query.within(0.2);
+    let (ret, trace) = query.explain_by_item(&rtxn, 0).unwrap().unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret.clone())), @r"
+    id(0): distance(0)
+    id(82): distance(0.079682656)
+    id(63): distance(0.14555506)
+    ");
+
+    // the branches of the trees out of the radius are skipped without missing any item
+    let (_, full_trace) = reader.nns(usize::MAX).explain_by_item(&rtxn, 0).unwrap().unwrap();
+    assert!(trace.nodes_popped < full_trace.nodes_popped);
+    let exact = reader.nns(usize::MAX).within(0.2).exhaustive().by_item(&rtxn, 0).unwrap();
+    assert_eq!(Some(ret.clone()), exact);
+    let iter = query.iter_by_item(&rtxn, 0).unwrap().unwrap();
+    assert_eq!(iter.collect::<Result<Vec<_>, _>>().unwrap(), ret);
+
+    // the count still caps the number of results
+    let ret = reader.nns(2).within(0.2).by_item(&rtxn, 0).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(0): distance(0)
+    id(82): distance(0.079682656)
+    ");
+
+    let ret = reader.nns(usize::MAX).within(0.15).by_vector(&rtxn, &[0.5, 0.5, 0.5]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(21): distance(0.06813112)
+    id(39): distance(0.13188721)
+    ");
+}
+
+#[test]
+fn radius_search_manhattan() {
+    let handle = create_database::<Manhattan>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Manhattan>::open(&rtxn, 0, handle.database).unwrap();
+
+    let mut query = reader.nns(usize::MAX);
+    query.within(0.3);
+    let (ret, trace) = query.explain_by_vector(&rtxn, &[0.5, 0.5, 0.5]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret.clone())), @r"
+    id(21): distance(0.102083206)
+    id(39): distance(0.18612552)
+    id(59): distance(0.28961593)
+    id(31): distance(0.2981609)
+    ");
+
+    // the branches of the trees out of the radius are skipped without missing any item
+    let (_, full_trace) =
+        reader.nns(usize::MAX).explain_by_vector(&rtxn, &[0.5, 0.5, 0.5]).unwrap();
+    assert!(trace.nodes_popped < full_trace.nodes_popped);
+    let exact = reader.nns(usize::MAX).within(0.3).exhaustive().by_vector(&rtxn, &[0.5, 0.5, 0.5]);
+    assert_eq!(ret, exact.unwrap());
+}
+
+#[test]
+fn radius_search_dot_product() {
+    let handle = create_database::<DotProduct>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<DotProduct>::open(&rtxn, 0, handle.database).unwrap();
+
+    // Only the items with a dot product higher than the threshold are returned
+    let mut query = reader.nns(usize::MAX);
+    query.within(2.3);
+    let ret = query.by_vector(&rtxn, &[1.0, 1.0, 1.0]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret.clone())), @r"
+    id(60): distance(2.6852489)
+    id(9): distance(2.684834)
+    id(99): distance(2.6354423)
+    id(2): distance(2.5317035)
+    id(29): distance(2.480845)
+    id(90): distance(2.4421184)
+    id(96): distance(2.3397958)
+    ");
+    let exact = query.exhaustive().by_vector(&rtxn, &[1.0, 1.0, 1.0]).unwrap();
+    assert_eq!(ret, exact);
+}
+
+#[test]
+fn exhaustive_search() {
+    let handle = create_database::<Euclidean>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    // even with a tiny search_k the results are exact
+    let mut query = reader.nns(5);
+    query.search_k(NonZeroUsize::new(1).unwrap());
+    let ret = query.by_item(&rtxn, 0).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(0): distance(0)
+    id(82): distance(0.079682656)
+    id(63): distance(0.14555506)
+    ");
+    let ret = query.exhaustive().by_item(&rtxn, 0).unwrap();
+    insta::assert_snapshot!(NnsRes(ret.clone()), @r"
+    id(0): distance(0)
+    id(82): distance(0.079682656)
+    id(63): distance(0.14555506)
+    id(44): distance(0.20759042)
+    id(78): distance(0.20985684)
+    ");
+    let mut wide_query = reader.nns(5);
+    wide_query.search_k(NonZeroUsize::new(10_000).unwrap());
+    assert_eq!(ret, wide_query.by_item(&rtxn, 0).unwrap());
+
+    let candidates = RoaringBitmap::from_iter([3, 27, 90, 1000]);
+    let ret = reader
+        .nns(5)
+        .candidates(&candidates)
+        .exhaustive()
+        .by_vector(&rtxn, &[0.5, 0.5, 0.5])
+        .unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(3): distance(0.5000592)
+    id(90): distance(0.58402485)
+    id(27): distance(0.6361435)
+    ");
+
+    // most of the items are candidates, the leafs are read with a single cursor
+    let candidates = RoaringBitmap::from_iter(20..80);
+    let ret = reader
+        .nns(3)
+        .candidates(&candidates)
+        .exhaustive()
+        .by_vector(&rtxn, &[0.5, 0.5, 0.5])
+        .unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret.clone())), @r"
+    id(21): distance(0.06813112)
+    id(39): distance(0.13188721)
+    id(59): distance(0.1951884)
+    ");
+    wide_query.candidates(&candidates);
+    assert_eq!(ret[..], wide_query.by_vector(&rtxn, &[0.5, 0.5, 0.5]).unwrap()[..3]);
+}
+
+#[test]
+fn exhaustive_search_cosine() {
+    let handle = create_database::<Cosine>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen::<f32>() - 0.5);
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Cosine>::open(&rtxn, 0, handle.database).unwrap();
+
+    // The query vector is normalized like the items, its norm doesn't change the distances
+    let mut query = reader.nns(5);
+    query.exhaustive();
+    let ret = query.by_vector(&rtxn, &[10.0, 0.0, 0.0]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret.clone())), @r"
+    id(98): distance(0.0046060383)
+    id(58): distance(0.007642716)
+    id(80): distance(0.018868834)
+    id(40): distance(0.050493777)
+    id(1): distance(0.081092864)
+    ");
+    let ids = |ret: Vec<(ItemId, f32)>| ret.into_iter().map(|(id, _)| id).collect::<Vec<_>>();
+    assert_eq!(ids(ret.clone()), ids(query.by_vector(&rtxn, &[0.1, 0.0, 0.0]).unwrap()));
+    let mut wide_query = reader.nns(5);
+    wide_query.search_k(NonZeroUsize::new(10_000).unwrap());
+    assert_eq!(ret, wide_query.by_vector(&rtxn, &[10.0, 0.0, 0.0]).unwrap());
+
+    let candidates = RoaringBitmap::from_iter((0..100).step_by(2));
+    let ret = query.candidates(&candidates).by_item(&rtxn, 0).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(0): distance(0)
+    id(82): distance(0.005233556)
+    id(56): distance(0.021404564)
+    id(44): distance(0.038245797)
+    id(78): distance(0.05524984)
+    ");
+}
+
+#[test]
+fn exhaustive_when_candidates_are_selective() {
+    let handle = create_line_database();
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    let candidates = RoaringBitmap::from_iter([3, 27, 90]);
+    let mut query = reader.nns(2);
+    query.search_k(NonZeroUsize::new(1).unwrap()).candidates(&candidates);
+    assert_eq!(query.strategy(), SearchStrategy::Trees);
+
+    // The items that are not part of the index are not taken into account
+    let candidates = RoaringBitmap::from_iter([3, 27, 90, 1000, 1001, 1002]);
+    query.candidates(&candidates).exhaustive_below(0.05);
+    assert_eq!(query.strategy(), SearchStrategy::Exhaustive);
+    let ret = query.by_vector(&rtxn, &[30.0, 0.0]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(27): distance(3)
+    id(3): distance(27)
+    ");
+
+    query.exhaustive_below(0.03);
+    assert_eq!(query.strategy(), SearchStrategy::Trees);
+}
+
+#[test]
+fn ensure_count_while_filtering() {
+    let handle = create_database::<Euclidean>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..1000 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    let candidates = RoaringBitmap::from_iter((0..1000).step_by(10));
+    let mut query = reader.nns(4);
+    query.search_k(NonZeroUsize::new(1).unwrap()).candidates(&candidates);
+    let ret = query.by_item(&rtxn, 0).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(0): distance(0)
+    id(920): distance(0.030201828)
+    ");
+
+    query.ensure_count(NonZeroUsize::new(1000).unwrap());
+    let ret = query.by_item(&rtxn, 0).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(0): distance(0)
+    id(920): distance(0.030201828)
+    id(310): distance(0.12140466)
+    id(580): distance(0.25354004)
+    ");
+
+    // The bound is respected
+    query.ensure_count(NonZeroUsize::new(1).unwrap());
+    let ret = query.by_item(&rtxn, 0).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(0): distance(0)
+    id(920): distance(0.030201828)
+    ");
+}
+
+#[test]
+fn ensure_count_while_filtering_dot_product() {
+    let handle = create_database::<DotProduct>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..1000 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen::<f32>() - 0.5);
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<DotProduct>::open(&rtxn, 0, handle.database).unwrap();
+
+    let candidates = RoaringBitmap::from_iter((0..1000).step_by(10));
+    let mut query = reader.nns(4);
+    query.search_k(NonZeroUsize::new(1).unwrap()).candidates(&candidates);
+    let ret = query.by_vector(&rtxn, &[1.0, 0.0, 0.0]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(200): distance(0.43349445)
+    ");
+
+    query.ensure_count(NonZeroUsize::new(1000).unwrap());
+    let ret = query.by_vector(&rtxn, &[1.0, 0.0, 0.0]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(230): distance(0.49151266)
+    id(860): distance(0.48711574)
+    id(450): distance(0.4837588)
+    id(200): distance(0.43349445)
+    ");
+}
+
+#[test]
+fn search_subset_of_trees() {
+    let handle = create_database::<Euclidean>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+    let roots: Vec<_> = reader.roots().collect();
+    assert_eq!(roots.len(), 10);
+
+    let mut query = reader.nns(3);
+    query.max_trees(NonZeroUsize::new(2).unwrap());
+    let (ret, trace) = query.explain_by_item(&rtxn, 0).unwrap().unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret.clone())), @r"
+    id(0): distance(0)
+    id(63): distance(0.14555506)
+    id(44): distance(0.20759042)
+    ");
+    insta::assert_debug_snapshot!(trace, @r"
+    QueryTrace {
+        strategy: Trees,
+        search_k: 6,
+        nodes_popped: 12,
+        split_planes: 10,
+        descendants: 2,
+        candidates_before_filter: 6,
+        candidates_after_filter: 6,
+        distance_computations: 5,
+        partial: false,
+    }
+    ");
+
+    // The first two trees are the same as an explicit subset of two trees
+    let mut query = reader.nns(3);
+    query.trees(&roots[..2]);
+    let (subset_ret, subset_trace) = query.explain_by_item(&rtxn, 0).unwrap().unwrap();
+    assert_eq!(ret, subset_ret);
+    assert_eq!(trace.nodes_popped, subset_trace.nodes_popped);
+
+    // The limit applies on the subset
+    query.trees(&roots[5..]).max_trees(NonZeroUsize::new(1).unwrap());
+    let (ret, trace) = query.explain_by_item(&rtxn, 0).unwrap().unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(0): distance(0)
+    id(82): distance(0.079682656)
+    id(63): distance(0.14555506)
+    ");
+    assert_eq!(trace.search_k, 3);
+
+    // Ids that are not roots are ignored
+    let subset = [roots[0], 1_000_000];
+    let mut query = reader.nns(3);
+    query.trees(&subset);
+    let (_, trace) = query.explain_by_item(&rtxn, 0).unwrap().unwrap();
+    assert_eq!(trace.search_k, 3);
+}
+
+#[test]
+fn search_subset_of_trees_cosine() {
+    let handle = create_database::<Cosine>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen::<f32>() - 0.5);
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Cosine>::open(&rtxn, 0, handle.database).unwrap();
+    let roots: Vec<_> = reader.roots().collect();
+
+    // Every tree is explored on its own, the last one misses the nearest item
+    let mut nearests = Vec::new();
+    for root in roots.chunks(1) {
+        let mut query = reader.nns(3);
+        query.trees(root);
+        let (ret, trace) = query.explain_by_vector(&rtxn, &[1.0, 0.0, 0.0]).unwrap();
+        assert_eq!(trace.search_k, 3);
+        nearests.push(ret[0].0);
+    }
+    insta::assert_debug_snapshot!(nearests, @r"
+    [
+        98,
+        98,
+        98,
+        98,
+        98,
+        98,
+        98,
+        98,
+        98,
+        58,
+    ]
+    ");
+
+    // Giving all the trees is the same as the default
+    let mut query = reader.nns(3);
+    query.trees(&roots);
+    let ret = query.by_vector(&rtxn, &[1.0, 0.0, 0.0]).unwrap();
+    assert_eq!(ret, reader.nns(3).by_vector(&rtxn, &[1.0, 0.0, 0.0]).unwrap());
+
+    let mut query = reader.nns(3);
+    query.trees(&roots[..3]);
+    let (ret, trace) = query.explain_by_vector(&rtxn, &[1.0, 0.0, 0.0]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(98): distance(0.004606068)
+    id(58): distance(0.007642716)
+    id(80): distance(0.018868834)
+    ");
+    insta::assert_debug_snapshot!(trace, @r"
+    QueryTrace {
+        strategy: Trees,
+        search_k: 9,
+        nodes_popped: 22,
+        split_planes: 18,
+        descendants: 4,
+        candidates_before_filter: 10,
+        candidates_after_filter: 10,
+        distance_computations: 5,
+        partial: false,
+    }
+    ");
+}
+
+#[test]
+fn explain_query() {
+    let handle = create_database::<Euclidean>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    let candidates = RoaringBitmap::from_iter((0..100).step_by(2));
+    let mut query = reader.nns(3);
+    query.candidates(&candidates);
+    let (ret, trace) = query.explain_by_item(&rtxn, 0).unwrap().unwrap();
+    assert_eq!(ret, query.by_item(&rtxn, 0).unwrap().unwrap());
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(0): distance(0)
+    id(82): distance(0.079682656)
+    id(44): distance(0.20759042)
+    ");
+    insta::assert_debug_snapshot!(trace, @r"
+    QueryTrace {
+        strategy: Trees,
+        search_k: 30,
+        nodes_popped: 86,
+        split_planes: 70,
+        descendants: 16,
+        candidates_before_filter: 42,
+        candidates_after_filter: 31,
+        distance_computations: 10,
+        partial: false,
+    }
+    ");
+
+    query.exhaustive();
+    let (_, trace) = query.explain_by_vector(&rtxn, &[0.5, 0.5, 0.5]).unwrap();
+    insta::assert_debug_snapshot!(trace, @r"
+    QueryTrace {
+        strategy: Exhaustive,
+        search_k: 0,
+        nodes_popped: 0,
+        split_planes: 0,
+        descendants: 0,
+        candidates_before_filter: 100,
+        candidates_after_filter: 50,
+        distance_computations: 50,
+        partial: false,
+    }
+    ");
+}
+
+#[test]
+fn explain_query_cosine() {
+    let handle = create_database::<Cosine>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen::<f32>() - 0.5);
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Cosine>::open(&rtxn, 0, handle.database).unwrap();
+
+    // The radius doesn't prune the trees of the cosine distance
+    let mut query = reader.nns(3);
+    query.within(0.1);
+    let (ret, trace) = query.explain_by_vector(&rtxn, &[1.0, 0.0, 0.0]).unwrap();
+    assert_eq!(ret, query.by_vector(&rtxn, &[1.0, 0.0, 0.0]).unwrap());
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(98): distance(0.004606068)
+    id(58): distance(0.007642716)
+    id(80): distance(0.018868834)
+    ");
+    insta::assert_debug_snapshot!(trace, @r"
+    QueryTrace {
+        strategy: Trees,
+        search_k: 30,
+        nodes_popped: 72,
+        split_planes: 59,
+        descendants: 13,
+        candidates_before_filter: 30,
+        candidates_after_filter: 30,
+        distance_computations: 10,
+        partial: false,
+    }
+    ");
+}
+
+#[test]
+fn exclude_query_item() {
+    let handle = create_database::<Euclidean>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    let mut query = reader.nns(3);
+    query.exclude_query_item();
+    let ret = query.by_item(&rtxn, 0).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(82): distance(0.079682656)
+    id(63): distance(0.14555506)
+    id(44): distance(0.20759042)
+    ");
+
+    // The vector queries are not affected
+    let vector = reader.item_vector(&rtxn, 0).unwrap().unwrap();
+    let ret = query.by_vector(&rtxn, &vector).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(0): distance(0)
+    id(82): distance(0.079682656)
+    id(63): distance(0.14555506)
+    ");
+
+    let ret = query.by_items(&rtxn, &[0, 99]).unwrap();
+    insta::assert_snapshot!(NnsRes(ret[0].clone()), @r"
+    id(82): distance(0.079682656)
+    id(63): distance(0.14555506)
+    id(44): distance(0.20759042)
+    ");
+    insta::assert_snapshot!(NnsRes(ret[1].clone()), @r"
+    id(90): distance(0.15985951)
+    id(60): distance(0.16954955)
+    id(33): distance(0.20982583)
+    ");
+
+    query.exhaustive();
+    let ret = query.by_item(&rtxn, 0).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(82): distance(0.079682656)
+    id(63): distance(0.14555506)
+    id(44): distance(0.20759042)
+    ");
+}
+
+#[test]
+fn exclude_query_item_dot_product() {
+    let handle = create_database::<DotProduct>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<DotProduct>::open(&rtxn, 0, handle.database).unwrap();
+
+    // The query item is not its own nearest neighbor with the dot product, it's excluded anyway
+    let ret = reader.nns(3).by_item(&rtxn, 0).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(48): distance(1.242743)
+    id(0): distance(1.181946)
+    id(8): distance(1.1738448)
+    ");
+    let mut query = reader.nns(100);
+    query.exclude_query_item();
+    let ret = query.by_item(&rtxn, 0).unwrap().unwrap();
+    assert_eq!(ret.len(), 99);
+    assert!(ret.iter().all(|&(id, _)| id != 0));
+    insta::assert_snapshot!(NnsRes(Some(ret[..3].to_vec())), @r"
+    id(9): distance(1.6043944)
+    id(29): distance(1.5432887)
+    id(60): distance(1.5319556)
+    ");
+}
+
+#[test]
+fn exclude_items() {
+    let handle = create_line_database();
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    let excluded = RoaringBitmap::from_iter(48..=51);
+    let mut query = reader.nns(3);
+    query.exclude(&excluded);
+    let ret = query.by_item(&rtxn, 50).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(52): distance(2)
+    id(47): distance(3)
+    id(53): distance(3)
+    ");
+
+    // It can be combined with the candidates
+    let candidates = RoaringBitmap::from_iter((0..100).step_by(2));
+    query.candidates(&candidates);
+    let ret = query.by_vector(&rtxn, &[50.0, 0.0]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(52): distance(2)
+    id(46): distance(4)
+    id(54): distance(4)
+    ");
+
+    query.exhaustive();
+    let ret = query.by_vector(&rtxn, &[50.0, 0.0]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(52): distance(2)
+    id(46): distance(4)
+    id(54): distance(4)
+    ");
+}
+
+#[test]
+fn filter_with_predicate() {
+    let handle = create_line_database();
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    let mut query = reader.nns(3);
+    query.filter(&|item| item % 3 == 0);
+    let ret = query.by_item(&rtxn, 50).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(51): distance(1)
+    id(48): distance(2)
+    id(54): distance(4)
+    ");
+
+    // It's applied after the candidates
+    let candidates = RoaringBitmap::from_iter((0..100).step_by(2));
+    query.candidates(&candidates);
+    let ret = query.by_vectors(&rtxn, &[[50.0, 0.0]]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret[0].clone())), @r"
+    id(48): distance(2)
+    id(54): distance(4)
+    id(42): distance(8)
+    ");
+
+    query.exhaustive();
+    let ret = query.by_vector(&rtxn, &[50.0, 0.0]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(48): distance(2)
+    id(54): distance(4)
+    id(42): distance(8)
+    ");
+
+    // It's called once per item even if the item is found in every tree
+    let calls = AtomicUsize::new(0);
+    let predicate = |item: ItemId| {
+        calls.fetch_add(1, Ordering::Relaxed);
+        item % 3 == 0
+    };
+    let mut query = reader.nns(3);
+    query.search_k(NonZeroUsize::new(1000).unwrap()).filter(&predicate);
+    let (ret, trace) = query.explain_by_item(&rtxn, 50).unwrap().unwrap();
+    assert_eq!(trace.candidates_before_filter, 1000);
+    assert_eq!(calls.load(Ordering::Relaxed), 100);
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(51): distance(1)
+    id(48): distance(2)
+    id(54): distance(4)
+    ");
+
+    calls.store(0, Ordering::Relaxed);
+    let iter = query.iter_by_item(&rtxn, 50).unwrap().unwrap();
+    assert_eq!(iter.count(), 34);
+    assert_eq!(calls.load(Ordering::Relaxed), 100);
+}
+
+#[test]
+fn filter_with_the_read_transaction() {
+    let handle = create_line_database();
+    // The items of the index 1 are the ones the predicate accepts
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 1, 2);
+    for i in (0..100).step_by(3) {
+        writer.add_item(&mut wtxn, i, &[0.0, 0.0]).unwrap();
+    }
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+    let database = handle.database;
+    let predicate = |item| database.get(&rtxn, &Key::item(1, item)).unwrap().is_some();
+    let mut query = reader.nns(3);
+    query.filter(&predicate);
+    let ret = query.by_item(&rtxn, 50).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(51): distance(1)
+    id(48): distance(2)
+    id(54): distance(4)
+    ");
+
+    // The batch queries call it from the calling thread
+    let ret = query.by_items(&rtxn, &[50, 10]).unwrap();
+    insta::assert_snapshot!(NnsRes(ret[1].clone()), @r"
+    id(9): distance(1)
+    id(12): distance(2)
+    id(6): distance(4)
+    ");
+
+    let group = |item| database.get(&rtxn, &Key::item(1, item)).unwrap().is_some() as u64;
+    let mut query = reader.nns(2);
+    query.collapse(&group, NonZeroUsize::new(1).unwrap());
+    let ret = query.by_vectors(&rtxn, &[[50.0, 0.0]]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret[0].clone())), @r"
+    id(50): distance(0)
+    id(51): distance(1)
+    ");
+}
+
+#[test]
+fn search_with_vectors() {
+    let handle = create_database::<Euclidean>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    let query = reader.nns(3);
+    let ret = query.by_item_with_vectors(&rtxn, 0).unwrap().unwrap();
+    let ret: String = ret
+        .iter()
+        .map(|(id, dist, vector)| format!("id({id}): distance({dist}) {vector:?}\n"))
+        .collect();
+    insta::assert_snapshot!(ret, @r"
+    id(0): distance(0) [0.8012779, 0.23713464, 0.6954617]
+    id(82): distance(0.079682656) [0.7816024, 0.2531222, 0.61991966]
+    id(63): distance(0.14555506) [0.73128194, 0.33943218, 0.6191593]
+    ");
+
+    let ret = query.by_vector_with_vectors(&rtxn, &[0.5, 0.5, 0.5]).unwrap();
+    let expected = query.by_vector(&rtxn, &[0.5, 0.5, 0.5]).unwrap();
+    assert_eq!(ret.len(), expected.len());
+    for ((item, distance, vector), (expected_item, expected_distance)) in
+        ret.into_iter().zip(expected)
+    {
+        assert_eq!((item, distance), (expected_item, expected_distance));
+        assert_eq!(Some(vector), reader.item_vector(&rtxn, item).unwrap());
+    }
+
+    assert!(query.by_item_with_vectors(&rtxn, 100).unwrap().is_none());
+}
+
+#[test]
+fn search_with_vectors_dot_product() {
+    let handle = create_database::<DotProduct>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen::<f32>() - 0.5);
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<DotProduct>::open(&rtxn, 0, handle.database).unwrap();
+
+    // The vectors are the ones of the items, without the extra dimension of the dot product
+    let mut query = reader.nns(3);
+    query.exclude_query_item();
+    let ret = query.by_item_with_vectors(&rtxn, 0).unwrap().unwrap();
+    let ret: String = ret
+        .iter()
+        .map(|(id, dist, vector)| format!("id({id}): distance({dist}) {vector:?}\n"))
+        .collect();
+    insta::assert_snapshot!(ret, @r"
+    id(93): distance(0.286961) [0.496297, -0.2067374, 0.42511445]
+    id(56): distance(0.2560243) [0.4030167, -0.2265284, 0.3840027]
+    id(8): distance(0.2388844) [0.49542952, -0.3488077, -0.010575056]
+    ");
+
+    let ret = query.by_vector_with_vectors(&rtxn, &[1.0, 0.0, 0.0]).unwrap();
+    for (item, _, vector) in ret {
+        assert_eq!(Some(vector), reader.item_vector(&rtxn, item).unwrap());
+    }
+}
+
+#[test]
+fn search_with_scratch() {
+    let handle = create_line_database();
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    let mut scratch = QueryScratch::new();
+    let mut results = Vec::new();
+    let query = reader.nns(3);
+    query.by_vector_with(&rtxn, &[50.0, 0.0], &mut scratch, &mut results).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(results.clone())), @r"
+    id(50): distance(0)
+    id(49): distance(1)
+    id(51): distance(1)
+    ");
+
+    // The buffers are reused and the output is cleared between the queries
+    for i in (0..100).step_by(7) {
+        let vector = [i as f32 + 0.25, 0.0];
+        query.by_vector_with(&rtxn, &vector, &mut scratch, &mut results).unwrap();
+        assert_eq!(results, query.by_vector(&rtxn, &vector).unwrap());
+    }
+
+    let err = query.by_vector_with(&rtxn, &[1.0], &mut scratch, &mut results).unwrap_err();
+    insta::assert_snapshot!(err, @"Invalid vector dimensions. Got 1 but expected 2");
+}
+
+#[test]
+fn iter_neighbors() {
+    let handle = create_database::<Euclidean>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    let query = reader.nns(3);
+    let iter = query.iter_by_item(&rtxn, 0).unwrap().unwrap();
+    let ret = iter.take(7).collect::<crate::Result<Vec<_>>>().unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(0): distance(0)
+    id(82): distance(0.079682656)
+    id(63): distance(0.14555506)
+    id(44): distance(0.20759042)
+    id(56): distance(0.21729903)
+    id(89): distance(0.28109977)
+    id(68): distance(0.3093027)
+    ");
+
+    // All the items are eventually returned
+    let iter = query.iter_by_vector(&rtxn, &[0.5, 0.5, 0.5]).unwrap();
+    assert_eq!(iter.count(), 100);
+
+    // Resuming the iterator gives the same results as never stopping it
+    let all = query.iter_by_vector(&rtxn, &[0.2, 0.8, 0.5]).unwrap().take(10);
+    let all = all.collect::<crate::Result<Vec<_>>>().unwrap();
+    let mut iter = query.iter_by_vector(&rtxn, &[0.2, 0.8, 0.5]).unwrap();
+    let mut pages = iter.by_ref().take(5).collect::<crate::Result<Vec<_>>>().unwrap();
+    let continuation = iter.into_continuation();
+    let iter = query.resume(&rtxn, continuation).take(5);
+    pages.extend(iter.collect::<crate::Result<Vec<_>>>().unwrap());
+    assert_eq!(all, pages);
+
+    let candidates = RoaringBitmap::from_iter((0..100).step_by(2));
+    let mut query = reader.nns(3);
+    query.candidates(&candidates).within(0.25);
+    let iter = query.iter_by_item(&rtxn, 0).unwrap().unwrap();
+    let ret = iter.collect::<crate::Result<Vec<_>>>().unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(0): distance(0)
+    id(82): distance(0.079682656)
+    id(44): distance(0.20759042)
+    id(78): distance(0.20985684)
+    id(56): distance(0.21729903)
+    id(40): distance(0.21952258)
+    ");
+
+    // Once cancelled the iterator only returns the items it already scored
+    let stop = AtomicBool::new(false);
+    let cancel = || stop.load(Ordering::Relaxed);
+    let mut query = reader.nns(3);
+    query.cancel(&cancel);
+    let mut iter = query.iter_by_item(&rtxn, 0).unwrap().unwrap();
+    let ret = iter.by_ref().take(3).collect::<crate::Result<Vec<_>>>().unwrap();
+    assert_eq!(ret.len(), 3);
+    stop.store(true, Ordering::Relaxed);
+    assert!(iter.count() < 97);
+    assert_eq!(query.iter_by_item(&rtxn, 0).unwrap().unwrap().count(), 0);
+}
+
+#[test]
+fn iter_neighbors_manhattan() {
+    let handle = create_database::<Manhattan>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Manhattan>::open(&rtxn, 0, handle.database).unwrap();
+
+    let query = reader.nns(3);
+    let iter = query.iter_by_vector(&rtxn, &[0.5, 0.5, 0.5]).unwrap();
+    let ret = iter.take(5).collect::<crate::Result<Vec<_>>>().unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(21): distance(0.102083206)
+    id(39): distance(0.18612552)
+    id(31): distance(0.2981609)
+    id(52): distance(0.3368761)
+    id(86): distance(0.36596113)
+    ");
+
+    // The iterator skips the branches out of the radius without missing any item
+    let mut query = reader.nns(3);
+    query.within(0.4);
+    let iter = query.iter_by_vector(&rtxn, &[0.5, 0.5, 0.5]).unwrap();
+    let ret = iter.collect::<crate::Result<Vec<_>>>().unwrap();
+    let exact = reader.nns(100).within(0.4).exhaustive().by_vector(&rtxn, &[0.5, 0.5, 0.5]);
+    assert_eq!(ret, exact.unwrap());
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(21): distance(0.102083206)
+    id(39): distance(0.18612552)
+    id(59): distance(0.28961593)
+    id(31): distance(0.2981609)
+    id(52): distance(0.3368761)
+    id(86): distance(0.36596113)
+    id(57): distance(0.36930728)
+    id(64): distance(0.37603945)
+    ");
+}
+
+#[test]
+fn aggregated_queries() {
+    let handle = create_line_database();
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    let vectors = [[20.0, 0.0], [80.0, 0.0]];
+    let mut query = reader.nns(4);
+    let ret = query.by_vectors_aggregated(&rtxn, &vectors, Aggregation::Min).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(20): distance(0)
+    id(80): distance(0)
+    id(19): distance(1)
+    id(21): distance(1)
+    ");
+    // The items in between the vectors are harder to find
+    let close_vectors = [[45.0, 0.0], [55.0, 0.0]];
+    let ret = query.by_vectors_aggregated(&rtxn, &close_vectors, Aggregation::Mean).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(47): distance(5.8309517)
+    id(46): distance(6.4031243)
+    id(54): distance(6.4031243)
+    id(45): distance(7.071068)
+    ");
+    let mut wide_query = reader.nns(4);
+    wide_query.search_k(NonZeroUsize::new(200).unwrap());
+    let ret = wide_query.by_vectors_aggregated(&rtxn, &close_vectors, Aggregation::Mean).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(50): distance(5)
+    id(49): distance(5.0990195)
+    id(51): distance(5.0990195)
+    id(48): distance(5.3851647)
+    ");
+    // The radius doesn't prune the trees of the aggregated queries, nor unbound their search_k
+    let mut within_query = reader.nns(4);
+    within_query.within(10.0);
+    let ret = within_query.by_vectors_aggregated(&rtxn, &close_vectors, Aggregation::Mean).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(47): distance(5.8309517)
+    id(46): distance(6.4031243)
+    id(54): distance(6.4031243)
+    id(45): distance(7.071068)
+    ");
+    let weights = Aggregation::WeightedSum(&[1.0, 0.0]);
+    let ret = query.by_vectors_aggregated(&rtxn, &vectors, weights).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(20): distance(0)
+    id(19): distance(1)
+    id(21): distance(1)
+    id(22): distance(2)
+    ");
+
+    let err = query.by_vectors_aggregated(&rtxn, &vectors, Aggregation::WeightedSum(&[1.0]));
+    insta::assert_snapshot!(err.unwrap_err(), @"Invalid number of weights. Got 1 but expected 2");
+
+    // The missing items are ignored along with their weight
+    query.exclude_query_item();
+    let weights = Aggregation::WeightedSum(&[1.0, 1.0, 0.0]);
+    let ret = query.by_items_aggregated(&rtxn, &[20, 1000, 21], weights).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(19): distance(1)
+    id(18): distance(2)
+    id(22): distance(2)
+    id(17): distance(3)
+    ");
+
+    // The query items are excluded whatever their order
+    let ret = query.by_items_aggregated(&rtxn, &[21, 20, 21], Aggregation::Mean).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(22): distance(1.4142135)
+    id(19): distance(1.7320508)
+    id(23): distance(2.380476)
+    id(18): distance(2.7080128)
+    ");
+}
+
+#[test]
+fn search_by_examples() {
+    let handle = create_line_database();
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    // The examples are not returned
+    let ret = reader.nns(3).by_examples(&rtxn, &[40, 42], &[], 0.5).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(41): distance(0)
+    id(39): distance(2)
+    id(43): distance(2)
+    ");
+
+    // The query is moved away from the negatives, the missing items are ignored
+    let ret = reader.nns(3).by_examples(&rtxn, &[40, 42, 1000], &[10], 0.5).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(36): distance(0)
+    id(35): distance(1)
+    id(37): distance(1)
+    ");
+
+    let ret = reader.nns(3).by_examples(&rtxn, &[1000], &[], 0.5).unwrap();
+    assert!(ret.is_empty());
+}
+
+#[test]
+fn search_by_examples_cosine() {
+    let handle = create_database::<Cosine>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 2);
+    writer.add_item(&mut wtxn, 0, &[10.0, 0.0]).unwrap();
+    writer.add_item(&mut wtxn, 1, &[0.0, 1.0]).unwrap();
+    writer.add_item(&mut wtxn, 2, &[1.0, 1.0]).unwrap();
+    writer.add_item(&mut wtxn, 3, &[1.0, 0.1]).unwrap();
+    writer.add_item(&mut wtxn, 4, &[0.1, 1.0]).unwrap();
+
+    writer.builder(&mut rng()).n_trees(1).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Cosine>::open(&rtxn, 0, handle.database).unwrap();
+
+    // The examples are normalized, the longest one doesn't attract the query
+    let ret = reader.nns(3).by_examples(&rtxn, &[0, 1], &[], 0.5).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(2): distance(0)
+    id(3): distance(0.113021344)
+    id(4): distance(0.113021344)
+    ");
+}
+
+#[test]
+fn similarity_scores() {
+    let handle = create_database::<Cosine>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen::<f32>() - 0.5);
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Cosine>::open(&rtxn, 0, handle.database).unwrap();
+
+    let ret = reader.nns(4).by_item(&rtxn, 0).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(0): distance(0)
+    id(89): distance(0.00050774217)
+    id(63): distance(0.003263384)
+    id(82): distance(0.005233556)
+    ");
+
+    // The cosine similarity, whatever the norm of the query
+    let query = [2.0, -1.0, 0.5];
+    let ret = reader.nns(4).similarity().by_vector(&rtxn, &query).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret.clone())), @r"
+    id(40): distance(0.97746825)
+    id(63): distance(0.97457665)
+    id(82): distance(0.9656391)
+    id(8): distance(0.961017)
+    ");
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    for (item, similarity) in ret {
+        let vector = reader.item_vector(&rtxn, item).unwrap().unwrap();
+        let dot: f32 = vector.iter().zip(query).map(|(x, y)| x * y).sum();
+        let expected = dot / (norm(&vector) * norm(&query));
+        assert!((similarity - expected).abs() < 1e-5, "{similarity} != {expected}");
+    }
+}
+
+#[test]
+fn similarity_scores_dot_product() {
+    let handle = create_database::<DotProduct>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen::<f32>() - 0.5);
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<DotProduct>::open(&rtxn, 0, handle.database).unwrap();
+
+    // The raw dot product
+    let query = [2.0, -1.0, 0.5];
+    let ret = reader.nns(4).similarity().by_vector(&rtxn, &query).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret.clone())), @r"
+    id(89): distance(1.5444272)
+    id(93): distance(1.4118886)
+    id(8): distance(1.3343792)
+    id(56): distance(1.2245631)
+    ");
+    for (item, similarity) in ret {
+        let vector = reader.item_vector(&rtxn, item).unwrap().unwrap();
+        let expected: f32 = vector.iter().zip(query).map(|(x, y)| x * y).sum();
+        assert!((similarity - expected).abs() < 1e-5, "{similarity} != {expected}");
+    }
+}
+
+#[test]
+fn similarity_scores_euclidean() {
+    let handle = create_database::<Euclidean>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    // The distances are mapped into ]0, 1]
+    let distances = reader.nns(4).by_item(&rtxn, 0).unwrap().unwrap();
+    let ret = reader.nns(4).similarity().by_item(&rtxn, 0).unwrap().unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret.clone())), @r"
+    id(0): distance(1)
+    id(82): distance(0.926198)
+    id(63): distance(0.8729393)
+    id(44): distance(0.8280953)
+    ");
+    for ((item, similarity), (expected_item, distance)) in ret.into_iter().zip(distances) {
+        assert_eq!(item, expected_item);
+        assert_eq!(similarity, 1.0 / (1.0 + distance));
+    }
+}
+
+#[test]
+fn search_multiple_indexes() {
+    let handle = create_database::<Euclidean>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let mut rng = rng();
+    for index in 0..2 {
+        let writer = Writer::new(handle.database, index, 3);
+        for id in 0..100 {
+            let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+            writer.add_item(&mut wtxn, id, &vector).unwrap();
+        }
+        writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    }
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader_a = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+    let reader_b = Reader::<Euclidean>::open(&rtxn, 1, handle.database).unwrap();
+    let (query_a, query_b) = (reader_a.nns(3), reader_b.nns(3));
+
+    let vector = [0.5, 0.5, 0.5];
+    insta::assert_snapshot!(NnsRes(Some(query_a.by_vector(&rtxn, &vector).unwrap())), @r"
+    id(21): distance(0.06813112)
+    id(39): distance(0.13188721)
+    id(59): distance(0.1951884)
+    ");
+    insta::assert_snapshot!(NnsRes(Some(query_b.by_vector(&rtxn, &vector).unwrap())), @r"
+    id(69): distance(0.092734754)
+    id(92): distance(0.12908638)
+    id(0): distance(0.15425654)
+    ");
+
+    let mut query = MultiQuery::new(4);
+    query.add(&query_a, 1.0).add(&query_b, 1.0);
+    let ret = query.by_vector(&rtxn, &vector).unwrap();
+    insta::assert_snapshot!(MultiRes(ret), @r"
+    index(0) id(21): score(0.9362147)
+    index(1) id(69): score(0.91513515)
+    index(1) id(92): score(0.88567185)
+    index(0) id(39): score(0.88348025)
+    ");
+
+    // The second index is less relevant
+    let mut query = MultiQuery::new(4);
+    query.add(&query_a, 1.0).add(&query_b, 0.5);
+    let ret = query.by_vector(&rtxn, &vector).unwrap();
+    insta::assert_snapshot!(MultiRes(ret), @r"
+    index(0) id(21): score(0.9362147)
+    index(0) id(39): score(0.88348025)
+    index(0) id(59): score(0.83668816)
+    index(1) id(69): score(0.45756757)
+    ");
+}
+
+#[test]
+fn search_multiple_indexes_with_different_distances() {
+    let handle = create_database::<Euclidean>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let mut rng = rng();
+    let writer = Writer::new(handle.database, 0, 3);
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen::<f32>() - 0.5);
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    let cosine = handle.database.remap_data_type::<NodeCodec<Cosine>>();
+    let writer = Writer::new(cosine, 1, 3);
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen::<f32>() - 0.5);
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader_a = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+    let reader_b = Reader::<Cosine>::open(&rtxn, 1, cosine).unwrap();
+    let (query_a, query_b) = (reader_a.nns(3), reader_b.nns(3));
+
+    // The cosine similarities are mapped into [0, 1] before being weighted
+    let vector = [0.5, -0.5, 0.0];
+    let mut query = MultiQuery::new(6);
+    query.add(&query_a, 1.0).add(&query_b, 0.5);
+    let ret = query.by_vector(&rtxn, &vector).unwrap();
+    insta::assert_snapshot!(MultiRes(ret.clone()), @r"
+    index(0) id(8): score(0.86833394)
+    index(0) id(11): score(0.8346588)
+    index(0) id(4): score(0.8322969)
+    index(1) id(40): score(0.49862468)
+    index(1) id(72): score(0.4925785)
+    index(1) id(65): score(0.49181575)
+    ");
+    let similarities = reader_b.nns(3).similarity().by_vector(&rtxn, &vector).unwrap();
+    for (index, item, score) in ret.into_iter().filter(|&(index, ..)| index == 1) {
+        let (_, similarity) = similarities.iter().find(|(id, _)| *id == item).unwrap();
+        assert_eq!((index, score), (1, 0.5 * (1.0 + similarity) / 2.0));
+    }
+
+    let mut query = MultiQuery::new(6);
+    query.add(&query_a, 1.0).add(&query_b, 2.0);
+    let ret = query.by_vector(&rtxn, &vector).unwrap();
+    insta::assert_snapshot!(MultiRes(ret), @r"
+    index(1) id(40): score(1.9944987)
+    index(1) id(72): score(1.970314)
+    index(1) id(65): score(1.967263)
+    index(0) id(8): score(0.86833394)
+    index(0) id(11): score(0.8346588)
+    index(0) id(4): score(0.8322969)
+    ");
+}
+
+#[test]
+fn diversified_search() {
+    let handle = create_database::<Euclidean>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    // 10 clusters of 10 near-duplicates, the item `i` is in the cluster `i / 10`
+    for cluster in 0..10 {
+        let center: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        for i in 0..10 {
+            let vector: [f32; 3] = std::array::from_fn(|d| center[d] + rng.gen::<f32>() * 0.01);
+            writer.add_item(&mut wtxn, cluster * 10 + i, &vector).unwrap();
+        }
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    let mut query = reader.nns(3);
+    let ret = query.by_item(&rtxn, 50).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(50): distance(0)
+    id(57): distance(0.0032246604)
+    id(52): distance(0.004018977)
+    ");
+
+    query.diversify(0.3, NonZeroUsize::new(30).unwrap());
+    let ret = query.by_item(&rtxn, 50).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(50): distance(0)
+    id(81): distance(0.6113055)
+    id(69): distance(0.5975231)
+    ");
+
+    // Without diversity it's a regular search
+    query.diversify(1.0, NonZeroUsize::new(30).unwrap());
+    let ret = query.by_item(&rtxn, 50).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(50): distance(0)
+    id(57): distance(0.0032246604)
+    id(52): distance(0.004018977)
+    ");
+}
+
+#[test]
+fn diversified_search_cosine() {
+    let handle = create_database::<Cosine>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    // 10 directions with 10 vectors of different norms each, the item `i` has the direction `i / 10`
+    for direction in 0..10 {
+        let unit: [f32; 3] = std::array::from_fn(|_| rng.gen::<f32>() - 0.5);
+        for i in 0..10 {
+            let norm = 1.0 + i as f32;
+            let vector: [f32; 3] =
+                std::array::from_fn(|d| unit[d] * norm + rng.gen::<f32>() * 0.01);
+            writer.add_item(&mut wtxn, direction * 10 + i, &vector).unwrap();
+        }
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Cosine>::open(&rtxn, 0, handle.database).unwrap();
+
+    // The vectors of the same direction are near-duplicates whatever their norm
+    let mut query = reader.nns(3);
+    let ret = query.by_item(&rtxn, 50).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(50): distance(0.000000059604645)
+    id(51): distance(0.00007620454)
+    id(52): distance(0.000077068806)
+    ");
+
+    query.diversify(0.3, NonZeroUsize::new(30).unwrap());
+    let ret = query.by_item(&rtxn, 50).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(50): distance(0.000000059604645)
+    id(60): distance(0.34732622)
+    id(40): distance(0.28103986)
+    ");
+}
+
+#[test]
+fn collapsed_search() {
+    let handle = create_database::<Euclidean>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    let ret = reader.nns(5).by_item(&rtxn, 0).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(0): distance(0)
+    id(82): distance(0.079682656)
+    id(63): distance(0.14555506)
+    id(44): distance(0.20759042)
+    id(78): distance(0.20985684)
+    ");
+
+    // The items are grouped by thirties, only the nearest item of each group is kept
+    let group = |item: ItemId| (item / 30) as u64;
+    let mut query = reader.nns(3);
+    query.collapse(&group, NonZeroUsize::new(1).unwrap());
+    let ret = query.by_item(&rtxn, 0).unwrap();
+    insta::assert_snapshot!(NnsRes(ret), @r"
+    id(0): distance(0)
+    id(82): distance(0.079682656)
+    id(44): distance(0.20759042)
+    ");
+
+    // Up to two items of the three nearest groups, the items are grouped by their last digit
+    let group = |item: ItemId| (item % 10) as u64;
+    query.collapse(&group, NonZeroUsize::new(2).unwrap());
+    let ret = query.by_vector(&rtxn, &[0.5, 0.5, 0.5]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(21): distance(0.06813112)
+    id(39): distance(0.13188721)
+    id(59): distance(0.1951884)
+    id(86): distance(0.22858849)
+    id(31): distance(0.23528555)
+    id(36): distance(0.27870432)
+    ");
+}
+
+#[test]
+fn collapsed_search_dot_product() {
+    let handle = create_database::<DotProduct>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen::<f32>() - 0.5);
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<DotProduct>::open(&rtxn, 0, handle.database).unwrap();
+
+    let ret = reader.nns(5).by_vector(&rtxn, &[1.0, 0.0, 0.0]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(93): distance(0.496297)
+    id(8): distance(0.49542952)
+    id(89): distance(0.46637052)
+    id(99): distance(0.45914662)
+    id(87): distance(0.4523834)
+    ");
+
+    // The highest dot product of each group of ten is kept
+    let group = |item: ItemId| (item / 10) as u64;
+    let mut query = reader.nns(5);
+    query.collapse(&group, NonZeroUsize::new(1).unwrap());
+    let ret = query.by_vector(&rtxn, &[1.0, 0.0, 0.0]).unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(93): distance(0.496297)
+    id(8): distance(0.49542952)
+    id(89): distance(0.46637052)
+    id(61): distance(0.4176039)
+    id(42): distance(0.41697305)
+    ");
+}
+
+#[test]
+fn interrupted_search() {
+    let handle = create_line_database();
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    let (ret, trace) = reader.nns(3).explain_by_item(&rtxn, 50).unwrap().unwrap();
+    assert!(!trace.partial);
+    assert_eq!(ret.len(), 3);
+
+    // A deadline in the past stops the query right away
+    let mut query = reader.nns(3);
+    query.deadline(std::time::Instant::now());
+    let (ret, trace) = query.explain_by_item(&rtxn, 50).unwrap().unwrap();
+    assert!(trace.partial);
+    assert!(ret.is_empty());
+
+    // An interrupted exploration still scores the candidates it collected
+    let calls = AtomicUsize::new(0);
+    let cancel = || calls.fetch_add(1, Ordering::Relaxed) >= 60;
+    let mut query = reader.nns(3);
+    query.cancel(&cancel);
+    let (ret, trace) = query.explain_by_item(&rtxn, 50).unwrap().unwrap();
+    assert!(trace.partial);
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(50): distance(0)
+    id(49): distance(1)
+    id(51): distance(1)
+    ");
+    insta::assert_debug_snapshot!(trace, @r"
+    QueryTrace {
+        strategy: Trees,
+        search_k: 30,
+        nodes_popped: 60,
+        split_planes: 57,
+        descendants: 3,
+        candidates_before_filter: 5,
+        candidates_after_filter: 5,
+        distance_computations: 3,
+        partial: true,
+    }
+    ");
+
+    // The first candidates are scored before the others, whatever their ids
+    let (_, full_trace) = reader.nns(3).explain_by_item(&rtxn, 50).unwrap().unwrap();
+    let calls = AtomicUsize::new(0);
+    let cancel = || calls.fetch_add(1, Ordering::Relaxed) > full_trace.nodes_popped;
+    let mut query = reader.nns(3);
+    query.cancel(&cancel);
+    let (ret, trace) = query.explain_by_item(&rtxn, 50).unwrap().unwrap();
+    assert!(trace.partial);
+    assert_eq!(trace.nodes_popped, full_trace.nodes_popped);
+    assert_eq!(trace.distance_computations, 4);
+    assert!(full_trace.distance_computations > 4);
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(50): distance(0)
+    id(49): distance(1)
+    id(51): distance(1)
+    ");
+}
+
+#[test]
+fn interrupted_search_scores_the_candidates_found() {
+    let handle = create_database::<Euclidean>();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 3);
+    let mut rng = rng();
+    for id in 0..1000 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+
+    // All the candidates found before the exploration is cancelled are scored
+    let calls = AtomicUsize::new(0);
+    let cancel = || calls.fetch_add(1, Ordering::Relaxed) >= 100;
+    let mut query = reader.nns(3);
+    query.cancel(&cancel);
+    let (ret, trace) = query.explain_by_item(&rtxn, 0).unwrap().unwrap();
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(0): distance(0)
+    id(920): distance(0.030201828)
+    id(344): distance(0.043090675)
+    ");
+    insta::assert_debug_snapshot!(trace, @r"
+    QueryTrace {
+        strategy: Trees,
+        search_k: 30,
+        nodes_popped: 100,
+        split_planes: 92,
+        descendants: 8,
+        candidates_before_filter: 20,
+        candidates_after_filter: 20,
+        distance_computations: 4,
+        partial: true,
+    }
+    ");
+
+    // The exhaustive search scores the first items even if the deadline is reached
+    let mut query = reader.nns(3);
+    query.exhaustive().deadline(std::time::Instant::now());
+    let (ret, trace) = query.explain_by_item(&rtxn, 0).unwrap().unwrap();
+    assert!(trace.partial);
+    insta::assert_snapshot!(NnsRes(Some(ret)), @r"
+    id(0): distance(0)
+    id(1): distance(0.52150494)
+    id(2): distance(0.80984324)
+    ");
+    insta::assert_debug_snapshot!(trace, @r"
+    QueryTrace {
+        strategy: Exhaustive,
+        search_k: 0,
+        nodes_popped: 0,
+        split_planes: 0,
+        descendants: 0,
+        candidates_before_filter: 1000,
+        candidates_after_filter: 1000,
+        distance_computations: 3,
+        partial: true,
+    }
+    ");
+}
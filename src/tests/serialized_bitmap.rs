@@ -0,0 +1,117 @@
+use proptest::collection::btree_set;
+use proptest::prelude::*;
+use roaring::RoaringBitmap;
+
+use crate::roaring::SerializedBitmap;
+
+proptest! {
+    #[test]
+    fn serialized_bitmap_without_runs(
+        items in btree_set(prop_oneof![0..10_000u32, 100_000..200_000u32, any::<u32>()], 0..10_000)
+    ) {
+        let bitmap = RoaringBitmap::from_sorted_iter(items).unwrap();
+        let mut bytes = Vec::new();
+        bitmap.serialize_into(&mut bytes).unwrap();
+
+        let serialized = SerializedBitmap::new(&bytes).unwrap();
+        prop_assert_eq!(serialized.len(), bitmap.len());
+        prop_assert!(serialized.iter().eq(bitmap.iter()));
+        prop_assert_eq!(serialized.as_bytes(), &bytes[..]);
+        prop_assert!(SerializedBitmap::new(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
+
+#[test]
+fn serialized_bitmap_with_bitmap_containers() {
+    // More than 4096 values in a container are stored in a bitmap of 1024 words
+    let bitmap: RoaringBitmap = (0..5000).chain((1 << 16) + 60_000..2 << 16).collect();
+    let mut bytes = Vec::new();
+    bitmap.serialize_into(&mut bytes).unwrap();
+
+    let serialized = SerializedBitmap::new(&bytes).unwrap();
+    assert_eq!(serialized.len(), bitmap.len());
+    assert!(serialized.iter().eq(bitmap.iter()));
+    assert!(SerializedBitmap::new(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn serialized_bitmap_with_runs() {
+    let mut bytes = Vec::new();
+    // The cookie with two containers, only the second one is a run container
+    bytes.extend_from_slice(&(12347u32 | (1 << 16)).to_le_bytes());
+    bytes.push(0b10);
+    // The key and the cardinality minus one of the containers
+    bytes.extend_from_slice(&[0, 0, 1, 0]);
+    bytes.extend_from_slice(&[2, 0, 4, 0]);
+    // An array container with two values
+    bytes.extend_from_slice(&[3, 0, 9, 0]);
+    // A run container with the runs 0..=1 and 65533..=65535
+    bytes.extend_from_slice(&[2, 0, 0, 0, 1, 0, 253, 255, 2, 0]);
+
+    let serialized = SerializedBitmap::new(&bytes).unwrap();
+    let expected = [3, 9, 2 << 16, (2 << 16) + 1, (3 << 16) - 3, (3 << 16) - 2, (3 << 16) - 1];
+    assert_eq!(serialized.len(), expected.len() as u64);
+    assert_eq!(serialized.iter().collect::<Vec<_>>(), expected);
+
+    let bitmap = RoaringBitmap::deserialize_from(&bytes[..]).unwrap();
+    assert!(serialized.iter().eq(bitmap.iter()));
+    assert!(SerializedBitmap::new(&bytes[..bytes.len() - 1]).is_err());
+}
+
+/// Serializes containers of the given keys, cardinalities minus one and stores,
+/// the run containers are flagged. There must be less than four containers.
+fn serialize_containers(containers: &[(u16, u16, bool, &[u8])]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&(12347u32 | ((containers.len() as u32 - 1) << 16)).to_le_bytes());
+    let runs = containers.iter().enumerate().filter(|(_, c)| c.2).map(|(i, _)| 1 << i);
+    bytes.push(runs.sum());
+    for (key, cardinality, _, _) in containers {
+        bytes.extend_from_slice(&key.to_le_bytes());
+        bytes.extend_from_slice(&cardinality.to_le_bytes());
+    }
+    for (_, _, _, store) in containers {
+        bytes.extend_from_slice(store);
+    }
+    bytes
+}
+
+#[test]
+fn serialized_bitmap_rejects_malformed_containers() {
+    let error = |bytes: Vec<u8>| SerializedBitmap::new(&bytes).err().unwrap().to_string();
+    let valid =
+        serialize_containers(&[(0, 1, false, &[3, 0, 9, 0]), (2, 4, true, &[1, 0, 2, 0, 4, 0])]);
+    assert!(SerializedBitmap::new(&valid).is_ok());
+
+    // The keys of the containers must be sorted
+    let bytes = serialize_containers(&[(2, 0, false, &[3, 0]), (0, 0, false, &[9, 0])]);
+    insta::assert_snapshot!(error(bytes), @"container keys are not sorted");
+
+    // The values of an array container must be sorted and unique
+    let bytes = serialize_containers(&[(0, 1, false, &[9, 0, 3, 0])]);
+    insta::assert_snapshot!(error(bytes), @"array values are not sorted");
+    let bytes = serialize_containers(&[(0, 1, false, &[3, 0, 3, 0])]);
+    insta::assert_snapshot!(error(bytes), @"array values are not sorted");
+
+    // A run can't go past the end of its container
+    let bytes = serialize_containers(&[(0, 4, true, &[1, 0, 253, 255, 4, 0])]);
+    insta::assert_snapshot!(error(bytes), @"run overflows its container");
+
+    // The runs must be sorted and must not overlap
+    let bytes = serialize_containers(&[(0, 4, true, &[2, 0, 10, 0, 2, 0, 0, 0, 1, 0])]);
+    insta::assert_snapshot!(error(bytes), @"runs are not sorted or overlap");
+    let bytes = serialize_containers(&[(0, 7, true, &[2, 0, 0, 0, 5, 0, 3, 0, 1, 0])]);
+    insta::assert_snapshot!(error(bytes), @"runs are not sorted or overlap");
+
+    // The runs must contain as many values as the cardinality
+    let bytes = serialize_containers(&[(0, 5, true, &[1, 0, 0, 0, 4, 0])]);
+    insta::assert_snapshot!(error(bytes), @"cardinality doesn't match the store");
+
+    // The bits of a bitmap container must match its cardinality
+    let mut store = vec![0; 8192];
+    store[..625].fill(u8::MAX);
+    let bytes = serialize_containers(&[(0, 4999, false, &store)]);
+    assert!(SerializedBitmap::new(&bytes).is_ok());
+    store[0] = 0;
+    let bytes = serialize_containers(&[(0, 4999, false, &store)]);
+    insta::assert_snapshot!(error(bytes), @"cardinality doesn't match the store");
+}
@@ -15,7 +15,7 @@ fn test_put_and_get_tmp_nodes() {
     let mut tmp_nodes = TmpNodes::<Cosine>::new().unwrap();
     for i in 0..10 {
         let node = Node::Descendants(Descendants {
-            descendants: Cow::Owned(RoaringBitmap::from_iter(&[i + 0, i + 1, i + 2])),
+            descendants: Cow::Owned(RoaringBitmap::from_iter(&[i, i + 1, i + 2])),
         });
         tmp_nodes.put(i, &node).unwrap();
     }
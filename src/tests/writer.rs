@@ -8,8 +8,9 @@ use roaring::RoaringBitmap;
 
 use super::{create_database, rng};
 use crate::distance::{BinaryQuantizedCosine, Cosine, DotProduct, Euclidean};
+use crate::version::VersionCodec;
 use crate::writer::{target_n_trees, BuildOption};
-use crate::{Database, Reader, Writer};
+use crate::{Database, Key, Reader, Writer};
 
 #[test]
 fn guess_right_number_of_tree_use_specified_number_of_trees() {
@@ -755,6 +756,57 @@ fn delete_one_item() {
     "#);
 }
 
+#[test]
+fn delete_multiple_items() {
+    let handle = create_database::<Euclidean>();
+    let mut rng = rng();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 2);
+    for i in 0..6 {
+        writer.add_item(&mut wtxn, i, &[i as f32, 0.]).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(1).build(&mut wtxn).unwrap();
+    let other = Writer::new(handle.database, 1, 2);
+    for i in 0..3 {
+        other.add_item(&mut wtxn, i, &[i as f32, 0.]).unwrap();
+    }
+    other.builder(&mut rng).n_trees(1).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 2);
+    let deleted = writer.del_items(&mut wtxn, &RoaringBitmap::from_iter([2, 4, 5, 42])).unwrap();
+    assert_snapshot!(format!("{deleted:?}"), @"RoaringBitmap<[2, 4, 5]>");
+    let deleted = writer.del_items(&mut wtxn, &RoaringBitmap::new()).unwrap();
+    assert!(deleted.is_empty());
+
+    writer.builder(&mut rng).n_trees(1).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    insta::assert_snapshot!(handle, @r#"
+    ==================
+    Dumping index 0
+    Root: Metadata { dimensions: 2, items: RoaringBitmap<[0, 1, 3]>, roots: [0], distance: "euclidean" }
+    Version: Version { major: 0, minor: 7, patch: 0 }
+    Tree 0: SplitPlaneNormal(SplitPlaneNormal<euclidean> { left: 2, right: 5, normal: Leaf { header: NodeHeaderEuclidean { bias: "-2.7500" }, vector: [1.0000, 0.0000] } })
+    Tree 2: Descendants(Descendants { descendants: [0, 1] })
+    Tree 5: Descendants(Descendants { descendants: [3] })
+    Item 0: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [0.0000, 0.0000] })
+    Item 1: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [1.0000, 0.0000] })
+    Item 3: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [3.0000, 0.0000] })
+    ==================
+    Dumping index 1
+    Root: Metadata { dimensions: 2, items: RoaringBitmap<[0, 1, 2]>, roots: [0], distance: "euclidean" }
+    Version: Version { major: 0, minor: 7, patch: 0 }
+    Tree 0: SplitPlaneNormal(SplitPlaneNormal<euclidean> { left: 1, right: 2, normal: Leaf { header: NodeHeaderEuclidean { bias: "-1.0556" }, vector: [1.0000, 0.0000] } })
+    Tree 1: Descendants(Descendants { descendants: [0, 1] })
+    Tree 2: Descendants(Descendants { descendants: [2] })
+    Item 0: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [0.0000, 0.0000] })
+    Item 1: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [1.0000, 0.0000] })
+    Item 2: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [2.0000, 0.0000] })
+    "#);
+}
+
 #[test]
 fn add_one_item_incrementally_in_an_empty_db() {
     let handle = create_database::<Euclidean>();
@@ -1260,6 +1312,194 @@ fn need_build() {
     assert!(writer.need_build(&wtxn).unwrap(), "because an item has been updated");
 }
 
+#[test]
+fn pending_updates() {
+    let handle = create_database::<Euclidean>();
+    let mut rng = rng();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 2);
+    for i in 0..4 {
+        writer.add_item(&mut wtxn, i, &[i as f32, 0.0]).unwrap();
+    }
+    let pending = writer.pending_updates(&wtxn).unwrap();
+    assert_snapshot!(format!("{pending:?}"), @"PendingUpdates { updated: RoaringBitmap<[0, 1, 2, 3]>, deleted: RoaringBitmap<[]> }");
+    writer.builder(&mut rng).build(&mut wtxn).unwrap();
+    let pending = writer.pending_updates(&wtxn).unwrap();
+    assert!(pending.updated.is_empty() && pending.deleted.is_empty());
+
+    writer.add_item(&mut wtxn, 1, &[1.0, 1.0]).unwrap();
+    writer.add_item(&mut wtxn, 5, &[5.0, 0.0]).unwrap();
+    writer.del_item(&mut wtxn, 2).unwrap();
+    writer.add_item(&mut wtxn, 6, &[6.0, 0.0]).unwrap();
+    writer.del_item(&mut wtxn, 6).unwrap();
+    let pending = writer.pending_updates(&wtxn).unwrap();
+    assert_snapshot!(format!("{pending:?}"), @"PendingUpdates { updated: RoaringBitmap<[1, 5]>, deleted: RoaringBitmap<[2, 6]> }");
+}
+
+#[test]
+fn insert_item_in_trees() {
+    let handle = create_database::<Euclidean>();
+    let mut rng = rng();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 2);
+    let err = writer.builder(&mut rng).insert_item(&mut wtxn, 0, &[0.0, 0.0]).unwrap_err();
+    assert_snapshot!(err, @"The trees have not been built after an update on index 0");
+
+    writer.builder(&mut rng).build(&mut wtxn).unwrap();
+    // The first item creates a tree in an empty index
+    writer.builder(&mut rng).insert_item(&mut wtxn, 0, &[0.0, 0.0]).unwrap();
+    let err = writer.builder(&mut rng).insert_item(&mut wtxn, 1, &[1.0]).unwrap_err();
+    assert_snapshot!(err, @"Invalid vector dimensions. Got 1 but expected 2");
+    wtxn.commit().unwrap();
+    insta::assert_snapshot!(handle, @r#"
+    ==================
+    Dumping index 0
+    Root: Metadata { dimensions: 2, items: RoaringBitmap<[0]>, roots: [0], distance: "euclidean" }
+    Version: Version { major: 0, minor: 7, patch: 0 }
+    Tree 0: Descendants(Descendants { descendants: [0] })
+    Item 0: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [0.0000, 0.0000] })
+    "#);
+
+    // The descendants are split once they contain more than `split_after` items
+    let mut wtxn = handle.env.write_txn().unwrap();
+    for i in 1..6 {
+        writer
+            .builder(&mut rng)
+            .split_after(2)
+            .insert_item(&mut wtxn, i, &[i as f32, 0.0])
+            .unwrap();
+    }
+    assert!(!writer.need_build(&wtxn).unwrap());
+    wtxn.commit().unwrap();
+    insta::assert_snapshot!(handle, @r#"
+    ==================
+    Dumping index 0
+    Root: Metadata { dimensions: 2, items: RoaringBitmap<[0, 1, 2, 3, 4, 5]>, roots: [0], distance: "euclidean" }
+    Version: Version { major: 0, minor: 7, patch: 0 }
+    Tree 0: SplitPlaneNormal(SplitPlaneNormal<euclidean> { left: 1, right: 2, normal: Leaf { header: NodeHeaderEuclidean { bias: "-0.7143" }, vector: [1.0000, 0.0000] } })
+    Tree 1: Descendants(Descendants { descendants: [0] })
+    Tree 2: SplitPlaneNormal(SplitPlaneNormal<euclidean> { left: 3, right: 4, normal: Leaf { header: NodeHeaderEuclidean { bias: "-2.0556" }, vector: [1.0000, 0.0000] } })
+    Tree 3: Descendants(Descendants { descendants: [1, 2] })
+    Tree 4: SplitPlaneNormal(SplitPlaneNormal<euclidean> { left: 5, right: 6, normal: Leaf { header: NodeHeaderEuclidean { bias: "4.3750" }, vector: [-1.0000, 0.0000] } })
+    Tree 5: Descendants(Descendants { descendants: [5] })
+    Tree 6: Descendants(Descendants { descendants: [3, 4] })
+    Item 0: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [0.0000, 0.0000] })
+    Item 1: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [1.0000, 0.0000] })
+    Item 2: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [2.0000, 0.0000] })
+    Item 3: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [3.0000, 0.0000] })
+    Item 4: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [4.0000, 0.0000] })
+    Item 5: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [5.0000, 0.0000] })
+    "#);
+
+    // An updated item is moved in the trees
+    let mut wtxn = handle.env.write_txn().unwrap();
+    writer.builder(&mut rng).split_after(2).insert_item(&mut wtxn, 0, &[6.0, 0.0]).unwrap();
+    wtxn.commit().unwrap();
+    insta::assert_snapshot!(handle, @r#"
+    ==================
+    Dumping index 0
+    Root: Metadata { dimensions: 2, items: RoaringBitmap<[0, 1, 2, 3, 4, 5]>, roots: [2], distance: "euclidean" }
+    Version: Version { major: 0, minor: 7, patch: 0 }
+    Tree 2: SplitPlaneNormal(SplitPlaneNormal<euclidean> { left: 3, right: 4, normal: Leaf { header: NodeHeaderEuclidean { bias: "-2.0556" }, vector: [1.0000, 0.0000] } })
+    Tree 3: Descendants(Descendants { descendants: [1, 2] })
+    Tree 4: SplitPlaneNormal(SplitPlaneNormal<euclidean> { left: 5, right: 6, normal: Leaf { header: NodeHeaderEuclidean { bias: "4.3750" }, vector: [-1.0000, 0.0000] } })
+    Tree 5: Descendants(Descendants { descendants: [0, 5] })
+    Tree 6: Descendants(Descendants { descendants: [3, 4] })
+    Item 0: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [6.0000, 0.0000] })
+    Item 1: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [1.0000, 0.0000] })
+    Item 2: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [2.0000, 0.0000] })
+    Item 3: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [3.0000, 0.0000] })
+    Item 4: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [4.0000, 0.0000] })
+    Item 5: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [5.0000, 0.0000] })
+    "#);
+
+    let rtxn = handle.env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, handle.database).unwrap();
+    let results = reader.nns(2).by_vector(&rtxn, &[6.0, 0.0]).unwrap();
+    assert_snapshot!(format!("{results:?}"), @"[(0, 0.0), (5, 1.0)]");
+    drop(rtxn);
+
+    // The split nodes are created after the last tree node instead of reusing the free ids
+    let mut wtxn = handle.env.write_txn().unwrap();
+    writer.builder(&mut rng).split_after(2).insert_item(&mut wtxn, 6, &[7.0, 0.0]).unwrap();
+    wtxn.commit().unwrap();
+    insta::assert_snapshot!(handle, @r#"
+    ==================
+    Dumping index 0
+    Root: Metadata { dimensions: 2, items: RoaringBitmap<[0, 1, 2, 3, 4, 5, 6]>, roots: [2], distance: "euclidean" }
+    Version: Version { major: 0, minor: 7, patch: 0 }
+    Tree 2: SplitPlaneNormal(SplitPlaneNormal<euclidean> { left: 3, right: 4, normal: Leaf { header: NodeHeaderEuclidean { bias: "-2.0556" }, vector: [1.0000, 0.0000] } })
+    Tree 3: Descendants(Descendants { descendants: [1, 2] })
+    Tree 4: SplitPlaneNormal(SplitPlaneNormal<euclidean> { left: 5, right: 6, normal: Leaf { header: NodeHeaderEuclidean { bias: "4.3750" }, vector: [-1.0000, 0.0000] } })
+    Tree 5: SplitPlaneNormal(SplitPlaneNormal<euclidean> { left: 7, right: 8, normal: Leaf { header: NodeHeaderEuclidean { bias: "6.0833" }, vector: [-1.0000, 0.0000] } })
+    Tree 6: Descendants(Descendants { descendants: [3, 4] })
+    Tree 7: Descendants(Descendants { descendants: [6] })
+    Tree 8: Descendants(Descendants { descendants: [0, 5] })
+    Item 0: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [6.0000, 0.0000] })
+    Item 1: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [1.0000, 0.0000] })
+    Item 2: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [2.0000, 0.0000] })
+    Item 3: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [3.0000, 0.0000] })
+    Item 4: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [4.0000, 0.0000] })
+    Item 5: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [5.0000, 0.0000] })
+    Item 6: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [7.0000, 0.0000] })
+    "#);
+
+    // The items cannot be inserted while updates are pending
+    let mut wtxn = handle.env.write_txn().unwrap();
+    writer.del_item(&mut wtxn, 1).unwrap();
+    let err = writer.builder(&mut rng).insert_item(&mut wtxn, 7, &[7.0, 0.0]).unwrap_err();
+    assert_snapshot!(err, @"The trees have not been built after an update on index 0");
+}
+
+#[test]
+fn insert_item_with_dot_product() {
+    let handle = create_database::<DotProduct>();
+    let mut rng = rng();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 2);
+    writer.add_item(&mut wtxn, 0, &[1.0, 0.0]).unwrap();
+    writer.add_item(&mut wtxn, 1, &[0.0, 2.0]).unwrap();
+    writer.builder(&mut rng).build(&mut wtxn).unwrap();
+    // The version is written like with a build
+    handle.database.remap_data_type::<VersionCodec>().delete(&mut wtxn, &Key::version(0)).unwrap();
+
+    // Only the new item is preprocessed when its norm is not the greatest one
+    writer.builder(&mut rng).insert_item(&mut wtxn, 2, &[1.0, 1.0]).unwrap();
+    wtxn.commit().unwrap();
+    insta::assert_snapshot!(handle, @r#"
+    ==================
+    Dumping index 0
+    Root: Metadata { dimensions: 2, items: RoaringBitmap<[0, 1, 2]>, roots: [0], distance: "dot-product" }
+    Version: Version { major: 0, minor: 7, patch: 0 }
+    Tree 0: SplitPlaneNormal(SplitPlaneNormal<dot-product> { left: 1, right: 2, normal: Leaf { header: NodeHeaderDotProduct { extra_dim: "-0.6721", norm: "0.0000" }, vector: [-0.3881, 0.6306] } })
+    Tree 1: Descendants(Descendants { descendants: [0, 2] })
+    Tree 2: Descendants(Descendants { descendants: [1] })
+    Item 0: Leaf(Leaf { header: NodeHeaderDotProduct { extra_dim: "1.7321", norm: "4.0000" }, vector: [1.0000, 0.0000] })
+    Item 1: Leaf(Leaf { header: NodeHeaderDotProduct { extra_dim: "0.0000", norm: "4.0000" }, vector: [0.0000, 2.0000] })
+    Item 2: Leaf(Leaf { header: NodeHeaderDotProduct { extra_dim: "1.4142", norm: "4.0000" }, vector: [1.0000, 1.0000] })
+    "#);
+
+    // Otherwise all the items are preprocessed again
+    let mut wtxn = handle.env.write_txn().unwrap();
+    writer.builder(&mut rng).insert_item(&mut wtxn, 3, &[3.0, 0.0]).unwrap();
+    wtxn.commit().unwrap();
+    insta::assert_snapshot!(handle, @r#"
+    ==================
+    Dumping index 0
+    Root: Metadata { dimensions: 2, items: RoaringBitmap<[0, 1, 2, 3]>, roots: [0], distance: "dot-product" }
+    Version: Version { major: 0, minor: 7, patch: 0 }
+    Tree 0: SplitPlaneNormal(SplitPlaneNormal<dot-product> { left: 1, right: 2, normal: Leaf { header: NodeHeaderDotProduct { extra_dim: "-0.6721", norm: "0.0000" }, vector: [-0.3881, 0.6306] } })
+    Tree 1: SplitPlaneNormal(SplitPlaneNormal<dot-product> { left: 3, right: 4, normal: Leaf { header: NodeHeaderDotProduct { extra_dim: "-0.2368", norm: "0.0000" }, vector: [-0.2356, 0.9426] } })
+    Tree 2: Descendants(Descendants { descendants: [1] })
+    Tree 3: Descendants(Descendants { descendants: [0, 3] })
+    Tree 4: Descendants(Descendants { descendants: [2] })
+    Item 0: Leaf(Leaf { header: NodeHeaderDotProduct { extra_dim: "2.8284", norm: "9.0000" }, vector: [1.0000, 0.0000] })
+    Item 1: Leaf(Leaf { header: NodeHeaderDotProduct { extra_dim: "2.2361", norm: "9.0000" }, vector: [0.0000, 2.0000] })
+    Item 2: Leaf(Leaf { header: NodeHeaderDotProduct { extra_dim: "2.6458", norm: "9.0000" }, vector: [1.0000, 1.0000] })
+    Item 3: Leaf(Leaf { header: NodeHeaderDotProduct { extra_dim: "0.0000", norm: "9.0000" }, vector: [3.0000, 0.0000] })
+    "#);
+}
+
 #[test]
 fn append() {
     let handle = create_database::<Euclidean>();
@@ -1304,6 +1544,85 @@ fn append() {
     writer.append_item(&mut wtxn, 0, &[0.0, 0.0]).unwrap();
 }
 
+#[test]
+fn add_items() {
+    let handle = create_database::<Euclidean>();
+    let mut rng = rng();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 2);
+
+    // The last vector of an item is kept
+    let items = [(3, [3.0, 0.0]), (0, [0.0, 0.0]), (1, [1.0, 0.0]), (3, [3.0, 3.0])];
+    writer.add_items(&mut wtxn, items).unwrap();
+    let err = writer.add_items(&mut wtxn, [(4, vec![4.0])]).unwrap_err();
+    assert_snapshot!(err, @"Invalid vector dimensions. Got 1 but expected 2");
+    writer.builder(&mut rng).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+    insta::assert_snapshot!(handle, @r#"
+    ==================
+    Dumping index 0
+    Root: Metadata { dimensions: 2, items: RoaringBitmap<[0, 1, 3]>, roots: [0], distance: "euclidean" }
+    Version: Version { major: 0, minor: 7, patch: 0 }
+    Tree 0: SplitPlaneNormal(SplitPlaneNormal<euclidean> { left: 1, right: 2, normal: Leaf { header: NodeHeaderEuclidean { bias: "-1.3134" }, vector: [0.7740, 0.6332] } })
+    Tree 1: Descendants(Descendants { descendants: [0, 1] })
+    Tree 2: Descendants(Descendants { descendants: [3] })
+    Item 0: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [0.0000, 0.0000] })
+    Item 1: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [1.0000, 0.0000] })
+    Item 3: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [3.0000, 3.0000] })
+    "#);
+
+    // The items are encoded by chunks
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 1, 2);
+    let items: Vec<_> = (0..10_000).map(|i| (i, vec![i as f32, 0.0])).collect();
+    writer.add_items(&mut wtxn, items.iter().map(|(i, v)| (*i, v))).unwrap();
+    assert_eq!(writer.iter(&wtxn).unwrap().count(), 10_000);
+    assert_eq!(writer.item_vector(&wtxn, 9_999).unwrap(), Some(vec![9_999.0, 0.0]));
+
+    // The sorted chunks after the last item are appended and the last vector is still kept
+    let items = [(10_001, [1.0, 0.0]), (10_000, [0.0, 0.0]), (10_001, [1.0, 1.0])];
+    writer.add_items(&mut wtxn, items).unwrap();
+    assert_eq!(writer.item_vector(&wtxn, 10_000).unwrap(), Some(vec![0.0, 0.0]));
+    assert_eq!(writer.item_vector(&wtxn, 10_001).unwrap(), Some(vec![1.0, 1.0]));
+    // But we can still write before the last item
+    writer.add_items(&mut wtxn, [(20_000, [2.0, 0.0]), (5, [5.0, 5.0])]).unwrap();
+    assert_eq!(writer.item_vector(&wtxn, 5).unwrap(), Some(vec![5.0, 5.0]));
+
+    // The chunks written before an invalid vector are kept
+    let writer = Writer::new(handle.database, 2, 2);
+    let items = (0..5_000).map(|i| (i, if i == 4_500 { vec![0.0] } else { vec![i as f32, 0.0] }));
+    let err = writer.add_items(&mut wtxn, items).unwrap_err();
+    assert_snapshot!(err, @"Invalid vector dimensions. Got 1 but expected 2");
+    assert_eq!(writer.iter(&wtxn).unwrap().count(), 4_096);
+}
+
+#[test]
+fn append_items() {
+    let handle = create_database::<Euclidean>();
+    let mut rng = rng();
+    let mut wtxn = handle.env.write_txn().unwrap();
+    let writer = Writer::new(handle.database, 0, 2);
+
+    writer.append_items(&mut wtxn, [(0, [0.0, 0.0]), (1, [0.1, 0.1])]).unwrap();
+    // The items appended before the error are kept
+    let err = writer.append_items(&mut wtxn, [(3, [0.3, 0.3]), (2, [0.2, 0.2])]).unwrap_err();
+    assert_snapshot!(err, @"Item cannot be appended into the database");
+    writer.builder(&mut rng).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+    insta::assert_snapshot!(handle, @r#"
+    ==================
+    Dumping index 0
+    Root: Metadata { dimensions: 2, items: RoaringBitmap<[0, 1, 3]>, roots: [0], distance: "euclidean" }
+    Version: Version { major: 0, minor: 7, patch: 0 }
+    Tree 0: SplitPlaneNormal(SplitPlaneNormal<euclidean> { left: 1, right: 2, normal: Leaf { header: NodeHeaderEuclidean { bias: "-0.1532" }, vector: [0.7071, 0.7071] } })
+    Tree 1: Descendants(Descendants { descendants: [0, 1] })
+    Tree 2: Descendants(Descendants { descendants: [3] })
+    Item 0: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [0.0000, 0.0000] })
+    Item 1: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [0.1000, 0.1000] })
+    Item 3: Leaf(Leaf { header: NodeHeaderEuclidean { bias: "0.0000" }, vector: [0.3000, 0.3000] })
+    "#);
+}
+
 #[test]
 fn prepare_changing_distance() {
     let handle = create_database::<Cosine>();
@@ -19,11 +19,11 @@ mod binary_quantized_test;
 pub trait UnalignedVectorCodec: std::borrow::ToOwned + Sized {
     /// Creates an unaligned vector from a slice of bytes.
     /// Don't allocate.
-    fn from_bytes(bytes: &[u8]) -> Result<Cow<UnalignedVector<Self>>, SizeMismatch>;
+    fn from_bytes(bytes: &[u8]) -> Result<Cow<'_, UnalignedVector<Self>>, SizeMismatch>;
 
     /// Creates an unaligned vector from a slice of f32.
     /// May allocate depending on the codec.
-    fn from_slice(slice: &[f32]) -> Cow<UnalignedVector<Self>>;
+    fn from_slice(slice: &[f32]) -> Cow<'_, UnalignedVector<Self>>;
 
     /// Creates an unaligned slice of f32 wrapper from a slice of f32.
     /// The slice is already known to be of the right length.
@@ -58,13 +58,13 @@ pub struct UnalignedVector<Codec: UnalignedVectorCodec> {
 impl<Codec: UnalignedVectorCodec> UnalignedVector<Codec> {
     /// Creates an unaligned vector from a slice of bytes.
     /// Don't allocate.
-    pub fn from_bytes(bytes: &[u8]) -> Result<Cow<UnalignedVector<Codec>>, SizeMismatch> {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Cow<'_, UnalignedVector<Codec>>, SizeMismatch> {
         Codec::from_bytes(bytes)
     }
 
     /// Creates an unaligned vector from a slice of f32.
     /// May allocate depending on the codec.
-    pub fn from_slice(slice: &[f32]) -> Cow<UnalignedVector<Codec>> {
+    pub fn from_slice(slice: &[f32]) -> Cow<'_, UnalignedVector<Codec>> {
         Codec::from_slice(slice)
     }
 
@@ -178,7 +178,7 @@ pub fn from_0_5_to_0_6<C: Distance>(
 /// - `SplitPlaneNormal::normal` is now `Option<u64>`
 /// - `SplitPlaneNormal::normal` is now `None` if the normal is the zero vector
 /// - `SplitPlaneNormal::normal` does not point to an item directly anymore and
-///     only store a simple `ItemId` instead of the `NodeId` we had before
+///   only store a simple `ItemId` instead of the `NodeId` we had before
 /// - The version must be written in each index
 pub fn from_0_6_to_current<C: Distance>(
     rtxn: &RoTxn,
@@ -24,6 +24,7 @@ use crate::item_iter::ItemIter;
 use crate::node::{Descendants, ItemIds, Leaf, SplitPlaneNormal};
 use crate::parallel::{
     ConcurrentNodeIds, ImmutableLeafs, ImmutableSubsetLeafs, ImmutableTrees, TmpNodes,
+    TmpNodesReader,
 };
 use crate::reader::item_leaf;
 use crate::unaligned_vector::UnalignedVector;
@@ -33,6 +34,9 @@ use crate::{
     Result,
 };
 
+/// The number of items encoded in parallel at once by [`Writer::add_items`] and [`Writer::append_items`].
+const ITEMS_CHUNK_SIZE: usize = 4096;
+
 /// The options available when building the arroy database.
 pub struct ArroyBuilder<'a, D: Distance, R: Rng + SeedableRng + Send + Sync> {
     writer: &'a Writer<D>,
@@ -49,6 +53,15 @@ pub struct WriterProgress {
     pub sub: Option<SubStep>,
 }
 
+/// The changes made to an index since its last build, see [`Writer::pending_updates`].
+#[derive(Debug, Clone, Default)]
+pub struct PendingUpdates {
+    /// The items that have been added or updated.
+    pub updated: RoaringBitmap,
+    /// The items that have been deleted.
+    pub deleted: RoaringBitmap,
+}
+
 /// When a `MainStep` takes too long, it may output a sub-step that gives you more details about the progression we've made on the current step.
 #[derive(Debug)]
 pub struct SubStep {
@@ -262,6 +275,34 @@ impl<'a, D: Distance, R: Rng + SeedableRng + Send + Sync> ArroyBuilder<'a, D, R>
     pub fn build(&mut self, wtxn: &mut RwTxn) -> Result<()> {
         self.writer.build(wtxn, self.rng, &self.inner)
     }
+
+    /// Adds an item associated to a vector in the database and inserts it right away
+    /// in the existing trees, the index can be queried without calling [`Self::build`].
+    ///
+    /// In every tree, the item is added to the descendants node it falls in and the node
+    /// is split once it contains more than [`Self::split_after`] items. Only the nodes on
+    /// the path of the item are read and only the updated ones are written. The number of
+    /// trees is only adjusted by a build. The index must be built and have no pending updates,
+    /// otherwise an [`Error::NeedBuild`] is returned.
+    ///
+    /// It is meant for low-volume streams of items: updating an existing item explores
+    /// all the trees to remove it first and, with the dot product distance, an item with
+    /// a greater norm than all the others makes all the items be preprocessed again.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use arroy::{Writer, distances::Euclidean};
+    /// # let (writer, mut wtxn): (Writer<Euclidean>, heed::RwTxn) = todo!();
+    /// use rand::rngs::StdRng;
+    /// use rand::SeedableRng;
+    /// let mut rng = StdRng::seed_from_u64(92);
+    /// writer.builder(&mut rng).split_after(100).insert_item(&mut wtxn, 42, &[0.0, 1.0])?;
+    /// # Ok::<(), arroy::Error>(())
+    /// ```
+    pub fn insert_item(&mut self, wtxn: &mut RwTxn, item: ItemId, vector: &[f32]) -> Result<()> {
+        self.writer.insert_item(wtxn, self.rng, &self.inner, item, vector)
+    }
 }
 
 /// A writer to store new items, remove existing ones,
@@ -356,6 +397,13 @@ impl<D: Distance> Writer<D> {
                 .is_none())
     }
 
+    /// Returns the items that have been added, updated or deleted since the last build.
+    ///
+    /// An item is considered deleted if it is no longer in the database.
+    pub fn pending_updates(&self, rtxn: &RoTxn) -> Result<PendingUpdates> {
+        pending_updates(self.database, self.index, rtxn)
+    }
+
     /// Returns `true` if the database contains the given item.
     pub fn contains_item(&self, rtxn: &RoTxn, item: ItemId) -> Result<bool> {
         self.database
@@ -420,6 +468,146 @@ impl<D: Distance> Writer<D> {
         Ok(())
     }
 
+    /// Add many items associated to their vectors in the database.
+    ///
+    /// The vectors are quantized and encoded in parallel, by chunks, before being written in the
+    /// order of their ids. If an id appears multiple times the last vector is kept, like with
+    /// [`Writer::add_item`]. The chunks whose keys would be stored after the last key of the
+    /// database are appended, see [`Writer::append_item`]. It only happens for the index with the
+    /// highest number and when the ids are greater than the ones already stored, the chunks of
+    /// the other indexes are inserted.
+    ///
+    /// The dimensions of the vectors are checked chunk by chunk, the chunks written before an
+    /// [`Error::InvalidVecDimension`] are kept in the database. Abort the transaction to discard them.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Writer, distances::Euclidean};
+    /// # let (writer, mut wtxn, vectors): (Writer<Euclidean>, heed::RwTxn, Vec<Vec<f32>>) = todo!();
+    /// writer.add_items(&mut wtxn, vectors.iter().enumerate().map(|(i, v)| (i as u32, v)))?;
+    /// # Ok::<(), arroy::Error>(())
+    /// ```
+    pub fn add_items<V>(
+        &self,
+        wtxn: &mut RwTxn,
+        items: impl IntoIterator<Item = (ItemId, V)>,
+    ) -> Result<()>
+    where
+        V: AsRef<[f32]> + Send + Sync,
+    {
+        self.write_items(wtxn, items, false)
+    }
+
+    /// Attempt to append many items into the database, see [`Writer::append_item`].
+    ///
+    /// The vectors are quantized and encoded in parallel, by chunks, before being appended.
+    /// The items must be sorted by strictly increasing ids.
+    ///
+    /// The ids and the dimensions of the vectors are checked chunk by chunk, the chunks written
+    /// before an [`Error::InvalidItemAppend`] or an [`Error::InvalidVecDimension`] are kept in
+    /// the database. Abort the transaction to discard them.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use arroy::{Writer, distances::Euclidean};
+    /// # let (writer, mut wtxn, vectors): (Writer<Euclidean>, heed::RwTxn, Vec<Vec<f32>>) = todo!();
+    /// writer.append_items(&mut wtxn, vectors.iter().enumerate().map(|(i, v)| (i as u32, v)))?;
+    /// # Ok::<(), arroy::Error>(())
+    /// ```
+    pub fn append_items<V>(
+        &self,
+        wtxn: &mut RwTxn,
+        items: impl IntoIterator<Item = (ItemId, V)>,
+    ) -> Result<()>
+    where
+        V: AsRef<[f32]> + Send + Sync,
+    {
+        self.write_items(wtxn, items, true)
+    }
+
+    fn write_items<V>(
+        &self,
+        wtxn: &mut RwTxn,
+        items: impl IntoIterator<Item = (ItemId, V)>,
+        append: bool,
+    ) -> Result<()>
+    where
+        V: AsRef<[f32]> + Send + Sync,
+    {
+        let mut items = items.into_iter();
+        let mut chunk = Vec::with_capacity(ITEMS_CHUNK_SIZE);
+        loop {
+            chunk.clear();
+            chunk.extend(items.by_ref().take(ITEMS_CHUNK_SIZE));
+            if chunk.is_empty() {
+                return Ok(());
+            }
+
+            if let Some((_, vector)) =
+                chunk.iter().find(|(_, v)| v.as_ref().len() != self.dimensions)
+            {
+                return Err(Error::InvalidVecDimension {
+                    expected: self.dimensions,
+                    received: vector.as_ref().len(),
+                });
+            }
+
+            let mut leafs: Vec<_> = chunk
+                .par_iter()
+                .map(|(item, vector)| {
+                    let vector = UnalignedVector::from_slice(vector.as_ref());
+                    (*item, Leaf { header: D::new_header(&vector), vector })
+                })
+                .collect();
+
+            // Writing the keys in order is faster, the sort is stable to keep the last vector of an item.
+            if !append {
+                leafs.par_sort_by_key(|(item, _)| *item);
+            }
+            let append_chunk = append
+                || leafs
+                    .first()
+                    .map_or(Ok(false), |(item, _)| self.is_after_last_key(wtxn, *item))?;
+
+            let mut leafs = leafs.into_iter().peekable();
+            while let Some((item, leaf)) = leafs.next() {
+                if !append && leafs.peek().is_some_and(|(next, _)| *next == item) {
+                    continue;
+                }
+                let key = Key::item(self.index, item);
+                if append_chunk {
+                    match self.database.put_with_flags(
+                        wtxn,
+                        PutFlags::APPEND,
+                        &key,
+                        &Node::Leaf(leaf),
+                    ) {
+                        Ok(()) => (),
+                        Err(heed::Error::Mdb(MdbError::KeyExist)) => {
+                            return Err(Error::InvalidItemAppend)
+                        }
+                        Err(e) => return Err(e.into()),
+                    }
+                } else {
+                    self.database.put(wtxn, &key, &Node::Leaf(leaf))?;
+                }
+                // We cannot append here because the items appear after the updated keys
+                let key = Key::updated(self.index, item);
+                self.database.remap_data_type::<Unit>().put(wtxn, &key, &())?;
+            }
+        }
+    }
+
+    /// Returns `true` if the item would be stored after the last key of the database
+    /// and can be appended, which is never the case for an index followed by another one.
+    fn is_after_last_key(&self, rtxn: &RoTxn, item: ItemId) -> Result<bool> {
+        let key = Key::item(self.index, item).to_bytes();
+        let last = self.database.remap_types::<Bytes, DecodeIgnore>().last(rtxn)?;
+        Ok(last.is_none_or(|(last, ())| last < &key[..]))
+    }
+
     /// Deletes an item stored in this database and returns `true` if it existed.
     pub fn del_item(&self, wtxn: &mut RwTxn, item: ItemId) -> Result<bool> {
         if self.database.delete(wtxn, &Key::item(self.index, item))? {
@@ -435,6 +623,35 @@ impl<D: Distance> Writer<D> {
         }
     }
 
+    /// Deletes the items of the bitmap stored in this database and returns the ones that existed.
+    ///
+    /// It is faster than calling [`Writer::del_item`] for each item as the stored items
+    /// are walked in order with a single cursor.
+    pub fn del_items(&self, wtxn: &mut RwTxn, items: &RoaringBitmap) -> Result<RoaringBitmap> {
+        let mut deleted = RoaringBitmap::new();
+        let (Some(min), Some(max)) = (items.min(), items.max()) else {
+            return Ok(deleted);
+        };
+
+        let range = Key::item(self.index, min)..=Key::item(self.index, max);
+        let mut cursor = self.database.remap_data_type::<DecodeIgnore>().range_mut(wtxn, &range)?;
+        while let Some((key, ())) = cursor.next().transpose()? {
+            if items.contains(key.node.item) {
+                // safety: we don't have any reference to the database
+                unsafe { cursor.del_current() }?;
+                deleted.insert(key.node.item);
+            }
+        }
+        drop(cursor);
+
+        let updated = self.database.remap_data_type::<Unit>();
+        for item in &deleted {
+            updated.put(wtxn, &Key::updated(self.index, item), &())?;
+        }
+
+        Ok(deleted)
+    }
+
     /// Removes everything in the database, user items and internal tree nodes.
     pub fn clear(&self, wtxn: &mut RwTxn) -> Result<()> {
         let mut cursor = self
@@ -536,8 +753,6 @@ impl<D: Distance> Writer<D> {
             concurrent_node_ids: &concurrent_node_ids,
         };
 
-        let files_tls = Arc::new(ThreadLocal::new());
-
         let mut descendants =
             self.insert_items_in_current_trees(rng, options, to_insert, &roots, &frozen_reader)?;
 
@@ -560,13 +775,233 @@ impl<D: Distance> Writer<D> {
             descendants.insert(new_id, item_indices.clone());
         }
 
+        let tmp_nodes =
+            self.make_trees_from_descendants(rng, options, &progress, &frozen_reader, descendants)?;
+        for tmp_nodes in tmp_nodes {
+            for (item_id, item_bytes) in tmp_nodes.to_insert() {
+                self.database.remap_data_type::<Bytes>().put(
+                    wtxn,
+                    &Key::tree(self.index, item_id),
+                    item_bytes,
+                )?;
+            }
+        }
+
+        tracing::debug!("write the metadata...");
+        (options.progress)(WriterProgress { main: MainStep::WriteTheMetadata, sub: None });
+        let metadata = Metadata {
+            dimensions: self.dimensions.try_into().unwrap(),
+            items: item_indices,
+            roots: ItemIds::from_slice(&roots),
+            distance: D::name(),
+        };
+        self.database.remap_data_type::<MetadataCodec>().put(
+            wtxn,
+            &Key::metadata(self.index),
+            &metadata,
+        )?;
+        self.database.remap_data_type::<VersionCodec>().put(
+            wtxn,
+            &Key::version(self.index),
+            &Version::current(),
+        )?;
+
+        Ok(())
+    }
+
+    fn insert_item<R: Rng + SeedableRng + Send + Sync>(
+        &self,
+        wtxn: &mut RwTxn,
+        rng: &mut R,
+        options: &BuildOption,
+        item: ItemId,
+        vector: &[f32],
+    ) -> Result<()> {
+        if vector.len() != self.dimensions {
+            return Err(Error::InvalidVecDimension {
+                expected: self.dimensions,
+                received: vector.len(),
+            });
+        }
+        if self.need_build(wtxn)? {
+            return Err(Error::NeedBuild(self.index));
+        }
+
+        let metadata = self
+            .database
+            .remap_data_type::<MetadataCodec>()
+            .get(wtxn, &Key::metadata(self.index))?
+            .ok_or(Error::MissingMetadata(self.index))?;
+        let mut item_indices = metadata.items;
+        let mut roots: Vec<_> = metadata.roots.iter().collect();
+        let to_insert = RoaringBitmap::from_iter([item]);
+
+        // The previous version of the item must be removed from the trees before inserting the new one.
+        if item_indices.contains(item) {
+            self.delete_items_from_trees(wtxn, options, &mut roots, &to_insert)?;
+        }
+        item_indices.insert(item);
+
+        let vector = UnalignedVector::from_slice(vector);
+        let mut leaf = Leaf { header: D::new_header(&vector), vector };
+        // Only the new leaf is preprocessed, unless it changes the preprocessing of every item.
+        let preprocessed = match self.first_leaf(wtxn)? {
+            Some(preprocessed) => D::preprocess_leaf(&mut leaf, &preprocessed),
+            None => false,
+        };
+        self.database.put(wtxn, &Key::item(self.index, item), &Node::Leaf(leaf))?;
+        if !preprocessed {
+            self.pre_process_items(wtxn, options)?;
+        }
+        // The preprocessing may have changed the header of the leaf.
+        let leaf = item_leaf(self.database, self.index, wtxn, item)?.unwrap().into_owned();
+
+        // The new tree nodes are created after the last one, the free ids are only reused by a build.
+        let last_tree_node = self
+            .database
+            .remap_key_type::<PrefixCodec>()
+            .rev_prefix_iter(wtxn, &Prefix::tree(self.index))?
+            .remap_types::<KeyCodec, DecodeIgnore>()
+            .next()
+            .transpose()?
+            .map(|(key, ())| key.node.item);
+        let concurrent_node_ids = ConcurrentNodeIds::after(last_tree_node);
+
+        let mut descendants = IntMap::with_hasher(BuildNoHashHasher::default());
+        for &root in &roots {
+            let (node, items) = self.descendants_of_leaf(wtxn, options, rng, root, &leaf)?;
+            descendants.insert(node, items | &to_insert);
+        }
+        if roots.is_empty() {
+            let root = concurrent_node_ids.next()?;
+            roots.push(root);
+            descendants.insert(root, to_insert);
+        }
+
+        // Only the descendants that became too large are exploded into new sub-trees.
+        let mut large_descendants = IntMap::with_hasher(BuildNoHashHasher::default());
+        for (node, items) in descendants {
+            if self.fit_in_descendant(options, items.len()) {
+                self.database.put(
+                    wtxn,
+                    &Key::tree(self.index, node),
+                    &Node::Descendants(Descendants { descendants: Cow::Owned(items) }),
+                )?;
+            } else {
+                large_descendants.insert(node, items);
+            }
+        }
+
+        if !large_descendants.is_empty() {
+            let large_items =
+                large_descendants.values().fold(RoaringBitmap::new(), |acc, items| acc | items);
+            let leafs =
+                ImmutableLeafs::new(wtxn, options, self.database, &large_items, self.index)?;
+            let trees = ImmutableTrees::empty();
+            let frozen_reader = FrozzenReader {
+                leafs: &leafs,
+                trees: &trees,
+                concurrent_node_ids: &concurrent_node_ids,
+            };
+
+            let progress = AtomicU64::new(0);
+            let tmp_nodes = self.make_trees_from_descendants(
+                rng,
+                options,
+                &progress,
+                &frozen_reader,
+                large_descendants,
+            )?;
+            for tmp_nodes in tmp_nodes {
+                for (item_id, item_bytes) in tmp_nodes.to_insert() {
+                    self.database.remap_data_type::<Bytes>().put(
+                        wtxn,
+                        &Key::tree(self.index, item_id),
+                        item_bytes,
+                    )?;
+                }
+            }
+        }
+
+        let metadata = Metadata {
+            dimensions: self.dimensions.try_into().unwrap(),
+            items: item_indices,
+            roots: ItemIds::from_slice(&roots),
+            distance: D::name(),
+        };
+        self.database.remap_data_type::<MetadataCodec>().put(
+            wtxn,
+            &Key::metadata(self.index),
+            &metadata,
+        )?;
+        self.database.remap_data_type::<VersionCodec>().put(
+            wtxn,
+            &Key::version(self.index),
+            &Version::current(),
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns the leaf of the first item of the index, if any.
+    fn first_leaf<'t>(&self, rtxn: &'t RoTxn) -> Result<Option<Leaf<'t, D>>> {
+        let first = self
+            .database
+            .remap_key_type::<PrefixCodec>()
+            .prefix_iter(rtxn, &Prefix::item(self.index))?
+            .remap_key_type::<KeyCodec>()
+            .next()
+            .transpose()?;
+        Ok(first.and_then(|(_, node)| node.leaf()))
+    }
+
+    /// Follows the split planes from the root down to the descendants node the leaf falls in.
+    /// Returns the id of this node and the items it contains.
+    fn descendants_of_leaf<R: Rng>(
+        &self,
+        rtxn: &RoTxn,
+        options: &BuildOption,
+        rng: &mut R,
+        root: ItemId,
+        leaf: &Leaf<D>,
+    ) -> Result<(ItemId, RoaringBitmap)> {
+        let mut current_node = root;
+        loop {
+            options.cancelled()?;
+            let key = Key::tree(self.index, current_node);
+            match self.database.get(rtxn, &key)?.ok_or(Error::missing_key(key))? {
+                Node::Leaf(_) => unreachable!(),
+                Node::Descendants(Descendants { descendants }) => {
+                    return Ok((current_node, descendants.into_owned()))
+                }
+                Node::SplitPlaneNormal(SplitPlaneNormal { normal, left, right }) => {
+                    current_node = match side_of(rng, normal.as_ref(), leaf) {
+                        Side::Left => left,
+                        Side::Right => right,
+                    };
+                }
+            }
+        }
+    }
+
+    /// Stores the descendants that fit and explodes the large ones into new sub-trees in parallel.
+    /// Returns the temporary files containing the tree nodes that must be written into the database.
+    fn make_trees_from_descendants<R: Rng + SeedableRng + Send + Sync>(
+        &self,
+        rng: &mut R,
+        options: &BuildOption,
+        progress: &AtomicU64,
+        frozen_reader: &FrozzenReader<D>,
+        descendants: IntMap<ItemId, RoaringBitmap>,
+    ) -> Result<Vec<TmpNodesReader>> {
+        let files_tls = Arc::new(ThreadLocal::new());
+
         // When a task fails, a message must be sent in this channel.
         // When a taks starts it must check if the channel contains something and stop asap.
         // The channel will be openend only after all the tasks have been stopped.
         let (error_snd, error_rcv) = bounded(1);
 
         rayon::scope(|s| {
-            let frozen_reader = &frozen_reader;
             let files_tls = files_tls.clone();
             let error_snd = error_snd.clone();
 
@@ -577,7 +1012,7 @@ impl<D: Distance> Writer<D> {
                     rng,
                     options,
                     &error_snd,
-                    Some(&progress),
+                    Some(progress),
                     None,
                     s,
                     frozen_reader,
@@ -595,37 +1030,7 @@ impl<D: Distance> Writer<D> {
         }
 
         let files_tls = Arc::into_inner(files_tls).expect("Threads have all finished their works");
-        for file in files_tls.into_iter() {
-            let tmp_nodes = file.into_inner().into_bytes_reader()?;
-            for (item_id, item_bytes) in tmp_nodes.to_insert() {
-                self.database.remap_data_type::<Bytes>().put(
-                    wtxn,
-                    &Key::tree(self.index, item_id),
-                    item_bytes,
-                )?;
-            }
-        }
-
-        tracing::debug!("write the metadata...");
-        (options.progress)(WriterProgress { main: MainStep::WriteTheMetadata, sub: None });
-        let metadata = Metadata {
-            dimensions: self.dimensions.try_into().unwrap(),
-            items: item_indices,
-            roots: ItemIds::from_slice(&roots),
-            distance: D::name(),
-        };
-        self.database.remap_data_type::<MetadataCodec>().put(
-            wtxn,
-            &Key::metadata(self.index),
-            &metadata,
-        )?;
-        self.database.remap_data_type::<VersionCodec>().put(
-            wtxn,
-            &Key::version(self.index),
-            &Version::current(),
-        )?;
-
-        Ok(())
+        files_tls.into_iter().map(|file| file.into_inner().into_bytes_reader()).collect()
     }
 
     /// Remove extraneous trees if any
@@ -1325,6 +1730,15 @@ fn randomly_split_children<R: Rng>(
     }
 }
 
+/// Returns the side of the split plane the leaf falls in when it is inserted in an existing tree.
+/// The children of a dummy normal are randomly assigned a side.
+fn side_of<D: Distance, R: Rng>(rng: &mut R, normal: Option<&Leaf<D>>, leaf: &Leaf<D>) -> Side {
+    match normal {
+        Some(normal) => D::side(normal, leaf),
+        None => Side::random(rng),
+    }
+}
+
 /// Clears everything but the leafs nodes (items).
 /// Starts from the last node and stops at the first leaf.
 fn clear_tree_nodes<D: Distance>(
@@ -1417,13 +1831,14 @@ fn insert_items_in_descendants_from_frozen_reader<D: Distance, R: Rng>(
             let mut right_ids = RoaringBitmap::new();
 
             match normal {
+                // The leafs are not needed to randomly split the children of a dummy normal.
                 None => {
                     randomly_split_children(rng, to_insert, &mut left_ids, &mut right_ids);
                 }
                 Some(ref normal) => {
                     for leaf in to_insert {
                         let node = frozen_reader.leafs.get(leaf)?.unwrap();
-                        match D::side(normal, &node) {
+                        match side_of(rng, Some(normal), &node) {
                             Side::Left => left_ids.insert(leaf),
                             Side::Right => right_ids.insert(leaf),
                         };
@@ -1487,13 +1902,14 @@ fn insert_items_in_descendants_from_tmpfile<D: Distance, R: Rng>(
             let mut right_ids = RoaringBitmap::new();
 
             match normal {
+                // The leafs are not needed to randomly split the children of a dummy normal.
                 None => {
                     randomly_split_children(rng, to_insert, &mut left_ids, &mut right_ids);
                 }
                 Some(ref normal) => {
                     for leaf in to_insert {
                         let node = frozen_reader.leafs.get(leaf)?.unwrap();
-                        match D::side(normal, &node) {
+                        match side_of(rng, Some(normal), &node) {
                             Side::Left => left_ids.insert(leaf),
                             Side::Right => right_ids.insert(leaf),
                         };
@@ -1582,3 +1998,27 @@ pub(crate) fn fit_in_memory<D: Distance, R: Rng>(
 
     Some(items)
 }
+
+/// Reads the `Updated` keys of the index and splits them by checking whether the items still exist.
+pub(crate) fn pending_updates<D: Distance>(
+    database: Database<D>,
+    index: u16,
+    rtxn: &RoTxn,
+) -> Result<PendingUpdates> {
+    let mut pending = PendingUpdates::default();
+    let iter = database
+        .remap_types::<PrefixCodec, DecodeIgnore>()
+        .prefix_iter(rtxn, &Prefix::updated(index))?
+        .remap_key_type::<KeyCodec>();
+    for result in iter {
+        let (key, _) = result?;
+        let item = key.node.item;
+        if database.remap_data_type::<DecodeIgnore>().get(rtxn, &Key::item(index, item))?.is_some()
+        {
+            pending.updated.push(item);
+        } else {
+            pending.deleted.push(item);
+        }
+    }
+    Ok(pending)
+}
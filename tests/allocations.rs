@@ -0,0 +1,83 @@
+//! This test binary installs its own global allocator to count the allocations,
+//! it must not slow down nor be disturbed by the other tests of the crate.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use arroy::distances::Euclidean;
+use arroy::{Database, QueryScratch, Reader, Writer};
+use heed::EnvOpenOptions;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use roaring::RoaringBitmap;
+
+/// Counts the allocations made by each thread, see [`count_allocations`].
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns the number of allocations made by the current thread while running `f`.
+fn count_allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+#[test]
+fn search_with_scratch_does_not_allocate() {
+    let dir = tempfile::tempdir().unwrap();
+    let env =
+        unsafe { EnvOpenOptions::new().map_size(200 * 1024 * 1024).open(dir.path()) }.unwrap();
+    let mut wtxn = env.write_txn().unwrap();
+    let database: Database<Euclidean> = env.create_database(&mut wtxn, None).unwrap();
+    let writer = Writer::new(database, 0, 3);
+    let mut rng = StdRng::from_seed(std::array::from_fn(|_| 42));
+    for id in 0..1000 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        writer.add_item(&mut wtxn, id, &vector).unwrap();
+    }
+    writer.builder(&mut rng).n_trees(10).build(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = env.read_txn().unwrap();
+    let reader = Reader::<Euclidean>::open(&rtxn, 0, database).unwrap();
+    let candidates = RoaringBitmap::from_iter((0..1000).step_by(2));
+
+    let mut scratch = QueryScratch::new();
+    let mut results = Vec::new();
+    let mut query = reader.nns(5);
+    query.candidates(&candidates);
+    // The first queries grow the buffers
+    for _ in 0..100 {
+        let vector: [f32; 3] = std::array::from_fn(|_| rng.gen());
+        query.by_vector_with(&rtxn, &vector, &mut scratch, &mut results).unwrap();
+    }
+
+    let vector = [0.5, 0.5, 0.5];
+    let allocations = count_allocations(|| {
+        query.by_vector_with(&rtxn, &vector, &mut scratch, &mut results).unwrap();
+    });
+    assert_eq!(allocations, 0);
+    assert_eq!(results, query.by_vector(&rtxn, &vector).unwrap());
+}